@@ -0,0 +1,169 @@
+use godot::prelude::*;
+use godot::classes::Camera2D;
+use godot::classes::ICamera2D;
+
+use crate::rng;
+use crate::rng::GameRng;
+use crate::util::KeyboardInput;
+
+/// Follows a target around the level, without ever showing anything outside the level's bounds.
+/// The camera belongs to the level manager rather than the player, so it keeps its zoom level when the player changes level.
+#[derive(GodotClass)]
+#[class(base=Camera2D)]
+pub struct GameCamera {
+	/// How quickly the camera catches up with its target. Higher is snappier.
+	#[export]
+	follow_speed: f32,
+	
+	/// Zoom levels to cycle through with the zoom keys, from furthest out to closest in.
+	#[export]
+	zoom_levels: PackedFloat32Array,
+	
+	/// Index of the zoom level to start at.
+	#[export]
+	zoom_level: i32,
+	
+	/// Furthest the camera can be thrown off its target when shaking, in pixels.
+	#[export]
+	shake_strength: f32,
+	
+	/// How much shaking wears off per second, where 1 is the strongest shake.
+	#[export]
+	shake_decay: f32,
+	
+	target: Option<Gd<Node2D>>,
+	bounds: Option<Rect2>,
+	trauma: f32,
+	rng: Option<Gd<GameRng>>,
+	base: Base<Camera2D>
+}
+
+#[godot_api]
+impl ICamera2D for GameCamera {
+	fn init(base: Base<Camera2D>) -> Self {
+		Self {
+			follow_speed: 8.0,
+			zoom_levels: PackedFloat32Array::from(&[1.0, 1.5, 2.0]),
+			zoom_level: 1,
+			shake_strength: 6.0,
+			shake_decay: 2.5,
+			target: None,
+			bounds: None,
+			trauma: 0.0,
+			rng: None,
+			base
+		}
+	}
+	
+	fn ready(&mut self) {
+		let zoom = self.current_zoom();
+		self.base_mut().set_zoom(Vector2::new(zoom, zoom));
+	}
+	
+	fn process(&mut self, delta: f64) {
+		let delta = delta as f32;
+		
+		// Zooming.
+		if KeyboardInput::get_zoom_in() { self.change_zoom(1); }
+		if KeyboardInput::get_zoom_out() { self.change_zoom(-1); }
+		
+		let zoom = self.base().get_zoom().x;
+		let zoom = zoom + (self.current_zoom() - zoom) * follow_weight(self.follow_speed, delta);
+		self.base_mut().set_zoom(Vector2::new(zoom, zoom));
+		
+		// Following.
+		if let Some(target) = self.target_position() {
+			let position = self.base().get_position();
+			let position = position.lerp(target, follow_weight(self.follow_speed, delta));
+			let position = self.clamp_to_bounds(position);
+			self.base_mut().set_position(position);
+		}
+		
+		// Shaking.
+		self.trauma = f32::max(0.0, self.trauma - self.shake_decay * delta);
+		let offset = self.shake_offset();
+		self.base_mut().set_offset(offset);
+	}
+}
+
+impl GameCamera {
+	/// Start following a new target around a new level, jumping straight to it rather than sweeping across from wherever we were.
+	pub fn follow(&mut self, target: Gd<Node2D>, bounds: Option<Rect2>) {
+		self.target = Some(target);
+		self.bounds = bounds;
+		self.snap_to_target();
+	}
+	
+	/// Shake the screen. An amount of 1 is the strongest shake; shakes add up, but never go beyond that.
+	pub fn shake(&mut self, amount: f32) {
+		self.trauma = f32::min(1.0, self.trauma + amount);
+	}
+	
+	/// Share the game's random number generator, so shakes come from the same seed as everything else.
+	pub fn set_rng(&mut self, rng: Gd<GameRng>) {
+		self.rng = Some(rng);
+	}
+	
+	fn snap_to_target(&mut self) {
+		if let Some(target) = self.target_position() {
+			let position = self.clamp_to_bounds(target);
+			self.base_mut().set_position(position);
+			self.base_mut().reset_smoothing();
+		}
+	}
+	
+	/// Where the target is, if it still exists.
+	fn target_position(&self) -> Option<Vector2> {
+		let target = self.target.as_ref()?;
+		if !target.is_instance_valid() { return None; }
+		Some(target.get_global_position())
+	}
+	
+	/// Keep the edges of the view inside the level. Levels smaller than the view are centred instead.
+	fn clamp_to_bounds(&self, position: Vector2) -> Vector2 {
+		let bounds = match self.bounds {
+			Some(x) => x,
+			None => return position
+		};
+		
+		let half_view = self.base().get_viewport_rect().size / self.base().get_zoom() / 2.0;
+		let clamp_axis = |value: f32, min: f32, max: f32, half: f32| {
+			if max - min <= half * 2.0 { (min + max) / 2.0 } else { value.clamp(min + half, max - half) }
+		};
+		
+		Vector2::new(
+			clamp_axis(position.x, bounds.position.x, bounds.end().x, half_view.x),
+			clamp_axis(position.y, bounds.position.y, bounds.end().y, half_view.y)
+		)
+	}
+	
+	fn current_zoom(&self) -> f32 {
+		let index = self.zoom_level.clamp(0, self.zoom_levels.len() as i32 - 1);
+		self.zoom_levels.get(index as usize).unwrap_or(1.0)
+	}
+	
+	fn change_zoom(&mut self, change: i32) {
+		if self.zoom_levels.is_empty() { return; }
+		self.zoom_level = (self.zoom_level + change).clamp(0, self.zoom_levels.len() as i32 - 1);
+	}
+	
+	/// Shaking gets stronger faster than trauma does, so small hits only give a little nudge.
+	fn shake_offset(&mut self) -> Vector2 {
+		if self.trauma <= 0.0 { return Vector2::ZERO; }
+		
+		let mut rng = match self.rng.clone() {
+			Some(x) => x,
+			None => return Vector2::ZERO
+		};
+		let mut rng = rng.bind_mut();
+		let rng = rng.stream(rng::stream::CAMERA);
+		
+		let strength = self.shake_strength * self.trauma * self.trauma;
+		Vector2::new(rng.range_f64(-1.0, 1.0) as f32, rng.range_f64(-1.0, 1.0) as f32) * strength
+	}
+}
+
+/// How far to move towards a target this frame, so that following is smooth regardless of frame rate.
+fn follow_weight(speed: f32, delta: f32) -> f32 {
+	1.0 - (-speed * delta).exp()
+}
@@ -3,47 +3,84 @@ use godot::builtin::Vector2;
 use godot::classes::TileMapLayer;
 use godot::classes::AStarGrid2D;
 
+use crate::error::GameError;
+use crate::error::GameResult;
+use crate::error::LogError;
 use crate::level;
+use crate::reservation::ReservationGrid;
+use crate::spatial::EntityIndex;
 use crate::util::IsometricFacing;
+use crate::util::TileGeometry;
 
+/// Everything that moves around the grid one tile at a time shares this: its facing, the step it's taking, the movement
+/// state machine, and the level's reservations, which it uses to claim each tile before stepping onto it. Each step also
+/// moves it in the level's entity index, so the index never has to be rebuilt.
 #[derive(Default)]
 pub struct MovingCharacter {
 	pub facing: IsometricFacing,
-	pub destination: Option<Vector2>,
+	pub movement: MovementState,
+	pub step: Option<Step>,
+	pub easing: Easing,
 	pub tilemap: Option<Gd<TileMapLayer>>,
 	pub nav: Option<Gd<AStarGrid2D>>,
+	reservations: Option<Gd<ReservationGrid>>,
+	pub index: Option<Gd<EntityIndex>>,
+	owner: Option<InstanceId>,
+	pub tile: TileGeometry,
 }
 
 impl MovingCharacter {
-	pub fn set_tilemap(&mut self, tilemap: Gd<TileMapLayer>) { self.tilemap = Some(tilemap); }
-	pub fn set_nav(&mut self, nav: Gd<AStarGrid2D>) { self.nav = Some(nav); }
+	/// Use a level's tilemap, and move in steps of its tile size.
+	pub fn set_tilemap(&mut self, tilemap: Gd<TileMapLayer>) {
+		self.tile = TileGeometry::from_tilemap(&tilemap);
+		self.tilemap = Some(tilemap);
+	}
+	
+	/// Use a level's navigation grid, reservations and entity index, reserving the tile we're standing on for `owner`. The
+	/// tilemap must already be set.
+	pub fn set_nav(&mut self, nav: Gd<AStarGrid2D>, reservations: Gd<ReservationGrid>, index: Gd<EntityIndex>, owner: InstanceId, position: Vector2) {
+		self.nav = Some(nav);
+		self.reservations = Some(reservations);
+		self.index = Some(index);
+		self.owner = Some(owner);
+		
+		let tile = self.get_gridpos(position);
+		self.reserve(tile).log_error("Two things are standing on the same tile");
+	}
+	
+	/// Whether we're part way through a step between two tiles.
+	pub fn is_moving(&self) -> bool { self.step.is_some() }
+	
+	/// Where the current step will finish, if we're moving.
+	pub fn destination(&self) -> Option<Vector2> { self.step.map(|step| step.destination) }
 	
 	/// Check whether the character has pathfinding data.
 	pub fn has_nav(&self) -> bool {
-		self.tilemap.is_some() && self.nav.is_some()
+		self.tilemap.is_some() && self.nav.is_some() && self.reservations.is_some() && self.index.is_some()
 	}
 	
 	/// Get the character's current location in grid coordinates.
 	pub fn get_gridpos(&self, position: Vector2) -> Vector2i {
 		let tilemap = self.tilemap.as_ref().unwrap();
-		level::global_to_grid(&tilemap, position)
+		level::global_to_grid(tilemap, position)
 	}
 	
 	/// Calculate the destination coordinates for movement. The destination is always 1 tile in the direction you're facing.
 	pub fn calculate_movement(&self, position: Vector2) -> Vector2 {
-		let movement_vector =  self.facing.get_movement_vector(32.0);
-		let destination = position + movement_vector;
-		destination
+		let movement_vector =  self.facing.get_movement_vector(self.tile);
+		position + movement_vector
 	}
 	
 	/// Get the grid coordinates of the tile in front of you.
 	pub fn calculate_movement_grid(&self, position: Vector2) -> Vector2i {
 		let tilemap = self.tilemap.as_ref().unwrap();
 		let destination = self.calculate_movement(position);
-		level::global_to_grid(&tilemap, destination)
+		level::global_to_grid(tilemap, destination)
 	}
 	
 	/// Check for collision in the direction you're currently facing. If you're allowed to move, move and return true.
+	/// The destination is reserved before we set off, and the tile we're leaving is released. We count as being on the
+	/// destination in the entity index from then on, just as we hold it in the reservations.
 	pub fn try_moving(&mut self, position: Vector2) -> bool {
 		if !self.has_nav() { return false; }
 		
@@ -51,43 +88,240 @@ impl MovingCharacter {
 		let destination = self.calculate_movement(position);
 		let destination_grid = self.calculate_movement_grid(position);
 		
-		// If the destination is occupied, we can't move.
-		let nav = self.nav.as_mut().unwrap();
-		if nav.is_point_solid(destination_grid) {
+		// If the destination is blocked or somebody else has reserved it, we can't move.
+		if self.reserve(destination_grid).is_err() {
 			return false;
 		}
+		let current = self.get_gridpos(position);
+		self.release(current);
+		if let (Some(index), Some(owner)) = (self.index.as_mut(), self.owner) {
+			index.bind_mut().move_to(owner, destination_grid);
+		}
 		
-		// And start moving by updating our destination.
-		self.destination = Some(destination);
+		// And start moving towards the destination.
+		self.step = Some(Step::new(position, destination));
 		true
 	}
 	
-	/// Continue moving towards our current destination. Returns the new position.
-	pub fn keep_moving(&mut self, mut position: Vector2, speed: f32, delta: f64) -> Vector2 {
-		let destination = self.destination.unwrap();
+	/// Try to step onto the tile we're facing straight away.
+	pub fn start_moving(&mut self, position: Vector2) -> bool {
+		if self.try_moving(position) {
+			self.movement = MovementState::Moving;
+			true
+		} else {
+			self.movement = MovementState::Idle;
+			false
+		}
+	}
+	
+	/// Run the movement state machine for one physics frame, at `speed` tiles per second.
+	pub fn update_movement(&mut self, position: Vector2, speed: f32, delta: f64) -> MovementUpdate {
+		match self.movement {
+			MovementState::Idle | MovementState::Busy => MovementUpdate::None,
+			MovementState::StartMoving => {
+				if self.start_moving(position) { MovementUpdate::Started } else { MovementUpdate::Blocked }
+			},
+			MovementState::Moving => {
+				let position = self.keep_moving(position, speed, delta);
+				
+				// If we're done moving, change to the idle state.
+				if self.is_moving() { return MovementUpdate::Moved(position); }
+				self.movement = MovementState::Idle;
+				MovementUpdate::Arrived(position)
+			}
+		}
+	}
+	
+	/// Continue moving towards our current destination, at `speed` tiles per second. Returns the new position.
+	pub fn keep_moving(&mut self, position: Vector2, speed: f32, delta: f64) -> Vector2 {
+		let step = match self.step.as_mut() {
+			Some(x) => x,
+			None => return position
+		};
 		
-		// Update our position.
-		let velocity = self.facing.get_movement_vector(32.0) * speed * (delta as f32);
-		position += velocity;
+		step.advance(speed, delta);
+		let position = step.position(self.easing);
+		if step.is_finished() { self.step = None; }
+		position
+	}
+	
+	/// Whichever tile we hold: our destination if we're moving, or our current location if not.
+	pub fn occupied_tile(&self, position: Vector2) -> Vector2i {
+		self.get_gridpos(self.destination().unwrap_or(position))
+	}
+	
+	/// Give up whichever tile we hold, and drop out of the entity index, when we're leaving the level for good.
+	pub fn leave(&mut self, position: Vector2) {
+		if !self.has_nav() { return; }
+		let tile = self.occupied_tile(position);
+		self.release(tile);
+		if let (Some(index), Some(owner)) = (self.index.as_mut(), self.owner) {
+			index.bind_mut().remove(owner);
+		}
+	}
 		
-		// Check if we have reached our destination.
-		if position.distance_to(destination) < 1.0 {
-			position = destination;
-			self.destination = None;
+	fn reserve(&mut self, tile: Vector2i) -> GameResult<()> {
+		let (reservations, owner) = match (self.reservations.as_mut(), self.owner) {
+			(Some(reservations), Some(owner)) => (reservations, owner),
+			_ => return Ok(())
+		};
+		reservations.bind_mut().reserve(tile, owner).map_err(|e| GameError::Reservation(tile, e))
+	}
+	
+	fn release(&mut self, tile: Vector2i) {
+		if let (Some(reservations), Some(owner)) = (self.reservations.as_mut(), self.owner) {
+			reservations.bind_mut().release(tile, owner);
 		}
-		position
 	}
 	
-	/// Update your facing to move into the specified adjacent tile.
-	pub fn face_tile(&mut self, position: Vector2, tile: Vector2i) {
+	/// Update your facing to move into the specified adjacent tile. Fails if the tile isn't adjacent, leaving the facing unchanged.
+	pub fn face_tile(&mut self, position: Vector2, tile: Vector2i) -> GameResult<()> {
 		let tilemap = self.tilemap.as_ref().unwrap();
 		
-		let tilepos = level::grid_to_global(&tilemap, tile);
+		let tilepos = level::grid_to_global(tilemap, tile);
 		let movement_vector = tilepos - position;
 		
-		match IsometricFacing::from_movement_vector(movement_vector, 32.0) {
-			Some(facing) => self.facing = facing,
-			None => panic!("Tried to face a non-adjacent or illegal tile!")
-		};
+		match IsometricFacing::from_movement_vector(movement_vector, self.tile) {
+			Some(facing) => {
+				self.facing = facing;
+				Ok(())
+			},
+			None => Err(GameError::NotAdjacent(level::global_to_grid(tilemap, position), tile))
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MovementState {
+	#[default]
+	Idle,
+	StartMoving,		// Step onto the tile in front on the next physics frame
+	Moving,
+	Busy				// Doing something else that has to finish before moving again, like attacking
+}
+
+/// What happened on a physics frame of movement, so the character can update its position and animation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MovementUpdate {
+	None,
+	Started,
+	Blocked,			// Asked to move, but something was in the way
+	Moved(Vector2),		// Part way through a step, at this position
+	Arrived(Vector2)	// Finished a step, at this position
+}
+
+/// How a step between two tiles speeds up and slows down.
+#[derive(GodotConvert, Var, Export, Clone, Copy, Default, Debug, PartialEq)]
+#[godot(via = GString)]
+pub enum Easing {
+	#[default]
+	Linear,
+	EaseIn,
+	EaseOut,
+	EaseInOut
+}
+
+impl Easing {
+	/// Turn how far through a step we are in time, from 0 to 1, into how far along the path we should be.
+	pub fn apply(&self, t: f32) -> f32 {
+		match self {
+			Easing::Linear => t,
+			Easing::EaseIn => t * t,
+			Easing::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+			Easing::EaseInOut => t * t * (3.0 - 2.0 * t)
+		}
+	}
+}
+
+/// A single step from one tile to the next, measured as progress from 0 to 1. However long a frame takes, or however
+/// fast we're going, progress stops at 1 so we always finish exactly on the destination.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Step {
+	pub origin: Vector2,
+	pub destination: Vector2,
+	pub progress: f32
+}
+
+impl Step {
+	pub fn new(origin: Vector2, destination: Vector2) -> Self {
+		Self { origin, destination, progress: 0.0 }
+	}
+	
+	/// Move along the step at `speed` tiles per second. The speed can change from one frame to the next.
+	pub fn advance(&mut self, speed: f32, delta: f64) {
+		let distance = f32::max(0.0, speed) * delta as f32;
+		self.progress = f32::min(1.0, self.progress + distance);
+	}
+	
+	pub fn is_finished(&self) -> bool { self.progress >= 1.0 }
+	
+	/// Where we are along the step.
+	pub fn position(&self, easing: Easing) -> Vector2 {
+		if self.is_finished() { return self.destination; }
+		self.origin.lerp(self.destination, easing.apply(self.progress))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	const ORIGIN: Vector2 = Vector2::new(0.0, 0.0);
+	const DESTINATION: Vector2 = Vector2::new(32.0, 16.0);
+	const EASINGS: [Easing; 4] = [Easing::Linear, Easing::EaseIn, Easing::EaseOut, Easing::EaseInOut];
+	
+	#[test]
+	fn huge_delta_lands_on_destination() {
+		let mut step = Step::new(ORIGIN, DESTINATION);
+		step.advance(2.0, 1000.0);
+		assert!(step.is_finished());
+		assert_eq!(step.progress, 1.0);
+		for easing in EASINGS {
+			assert_eq!(step.position(easing), DESTINATION);
+		}
+	}
+	
+	#[test]
+	fn tiny_deltas_arrive() {
+		let mut step = Step::new(ORIGIN, DESTINATION);
+		let mut frames = 0;
+		while !step.is_finished() {
+			step.advance(2.0, 1.0 / 600.0);
+			frames += 1;
+			assert!(frames <= 301, "never arrived");
+		}
+		assert!(frames >= 300);
+		assert_eq!(step.position(Easing::Linear), DESTINATION);
+	}
+	
+	#[test]
+	fn speed_change_mid_step() {
+		let mut step = Step::new(ORIGIN, DESTINATION);
+		step.advance(1.0, 0.25);
+		assert_eq!(step.position(Easing::Linear), ORIGIN.lerp(DESTINATION, 0.25));
+		
+		// Sprinting partway through carries on from where we were, rather than jumping.
+		step.advance(3.0, 0.125);
+		assert_eq!(step.progress, 0.625);
+		step.advance(3.0, 0.125);
+		assert!(step.is_finished());
+	}
+	
+	#[test]
+	fn no_speed_doesnt_move() {
+		let mut step = Step::new(ORIGIN, DESTINATION);
+		step.advance(0.0, 1.0);
+		step.advance(-5.0, 1.0);
+		assert_eq!(step.progress, 0.0);
+		assert!(!step.is_finished());
+		assert_eq!(step.position(Easing::EaseInOut), ORIGIN);
+	}
+	
+	#[test]
+	fn easing_ends() {
+		for easing in EASINGS {
+			assert_eq!(easing.apply(0.0), 0.0, "{:?}", easing);
+			assert_eq!(easing.apply(1.0), 1.0, "{:?}", easing);
+		}
 	}
 }
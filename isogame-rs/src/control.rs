@@ -2,6 +2,18 @@ use godot::prelude::*;
 use godot::classes::ProgressBar;
 use godot::classes::IProgressBar;
 use godot::classes::StyleBoxFlat;
+use godot::classes::Label;
+use godot::classes::ILabel;
+use godot::classes::Control;
+use godot::classes::Button;
+use godot::classes::VBoxContainer;
+use godot::classes::PanelContainer;
+use godot::classes::ColorRect;
+use godot::classes::IColorRect;
+
+use crate::error::GameError;
+use crate::error::GameResult;
+use crate::error::LogError;
 
 #[derive(GodotClass)]
 #[class(base=ProgressBar,init)]
@@ -22,20 +34,187 @@ impl HealthBar {
 	pub fn update(&mut self, value: f64) {
 		self.base_mut().set_value(value);
 		self.base_mut().show();
-		self.set_color();
+		self.set_color().log_error("HealthBar couldn't change colour");
 		self.timer = 3.0;
 	}
 	
-	pub fn set_color(&mut self) {
-		let mut stylebox : Gd<StyleBoxFlat> = self.base().get_theme_stylebox("fill").unwrap().cast();
+	/// Colour the bar red, yellow or green depending on its value, which must be between 0 and 100.
+	pub fn set_color(&mut self) -> GameResult<()> {
+		let mut stylebox : Gd<StyleBoxFlat> = self.base().get_theme_stylebox("fill")
+			.and_then(|stylebox| stylebox.try_cast().ok())
+			.ok_or_else(|| GameError::WrongType("fill".to_string(), "StyleBoxFlat".to_string()))?;
 		
 		let color = match self.base().get_value() {
 			0.0..33.0 => Color::from_rgb(255.0, 0.0, 0.0),
 			33.0..66.0 => Color::from_rgb(255.0, 255.0, 0.0),
 			66.0..=100.0 => Color::from_rgb(0.0, 255.0, 0.0),
-			_ => panic!()
+			value => return Err(GameError::OutOfRange(value, 0.0, 100.0))
 		};
 		
 		stylebox.set_bg_color(color);
+		Ok(())
+	}
+}
+
+/// Shows how much stamina is left while it's being used up or recovering, and hides itself when it's full.
+#[derive(GodotClass)]
+#[class(base=ProgressBar,init)]
+pub struct StaminaBar {
+	base: Base<ProgressBar>
+}
+
+impl StaminaBar {
+	/// Update the bar with the current stamina, as a percentage of the maximum.
+	pub fn update(&mut self, value: f64) {
+		self.base_mut().set_value(value);
+		self.base_mut().set_visible(value < 100.0);
+	}
+}
+
+#[derive(GodotClass)]
+#[class(base=Label,init)]
+pub struct MessageBox {
+	timer: f64,
+	base: Base<Label>
+}
+
+#[godot_api]
+impl ILabel for MessageBox {
+	fn process(&mut self, delta: f64) {
+		self.timer = f64::max(0.00, self.timer - delta);
+		if self.timer == 0.00 { self.base_mut().hide(); }
+	}
+}
+
+impl MessageBox {
+	pub fn update(&mut self, text: GString) {
+		self.base_mut().set_text(&text);
+		self.base_mut().show();
+		self.timer = 3.0;
+	}
+}
+
+#[derive(GodotClass)]
+#[class(base=Control,init)]
+pub struct DialogueBox {
+	base: Base<Control>
+}
+
+#[godot_api]
+impl DialogueBox {
+	/// Emitted when the player picks a choice. The index is -1 if there were no choices to pick from.
+	#[signal]
+	pub fn choice_selected(index: i64);
+}
+
+impl DialogueBox {
+	pub fn display(&mut self, speaker: &str, text: &str, choices: &[String]) {
+		let mut speaker_label : Gd<Label> = self.base().get_node_as("Layout/Speaker");
+		speaker_label.set_text(speaker);
+		speaker_label.set_visible(!speaker.is_empty());
+		
+		let mut text_label : Gd<Label> = self.base().get_node_as("Layout/Text");
+		text_label.set_text(text);
+		
+		// Replace the old choices with new buttons.
+		self.clear_choices();
+		let mut container : Gd<VBoxContainer> = self.base().get_node_as("Layout/Choices");
+		let gd = self.to_gd();
+		
+		let mut buttons : Vec<(i64, &str)> = choices.iter().enumerate().map(|(i, choice)| (i as i64, choice.as_str())).collect();
+		if buttons.is_empty() { buttons.push((-1, "Continue")); }
+		
+		for (index, label) in buttons {
+			let mut button = Button::new_alloc();
+			button.set_text(label);
+			button.signals().pressed().connect_other(&gd, move |this: &mut Self| this.on_choice_pressed(index));
+			container.add_child(&button);
+			if index <= 0 { button.call_deferred("grab_focus", &[]); }
+		}
+		
+		self.base_mut().show();
+	}
+	
+	pub fn close(&mut self) {
+		self.clear_choices();
+		self.base_mut().hide();
+	}
+	
+	fn clear_choices(&mut self) {
+		let container : Gd<VBoxContainer> = self.base().get_node_as("Layout/Choices");
+		for mut child in container.get_children().iter_shared() {
+			child.queue_free();
+		}
+	}
+	
+	fn on_choice_pressed(&mut self, index: i64) {
+		let mut sig = self.signals().choice_selected();
+		sig.emit(index);
+	}
+}
+
+#[derive(GodotClass)]
+#[class(base=PanelContainer,init)]
+pub struct QuestLogPanel {
+	base: Base<PanelContainer>
+}
+
+impl QuestLogPanel {
+	pub fn toggle(&mut self) {
+		let visible = self.base().is_visible();
+		self.base_mut().set_visible(!visible);
+	}
+	
+	pub fn set_text(&mut self, text: &str) {
+		let mut label : Gd<Label> = self.base().get_node_as("Text");
+		label.set_text(text);
+	}
+}
+
+/// A full screen overlay that fades to and from black when changing level.
+#[derive(GodotClass)]
+#[class(base=ColorRect,init)]
+pub struct ScreenFade {
+	target: f32,
+	speed: f32,
+	base: Base<ColorRect>
+}
+
+#[godot_api]
+impl IColorRect for ScreenFade {
+	fn process(&mut self, delta: f64) {
+		let mut color = self.base().get_color();
+		let step = self.speed * delta as f32;
+		color.a = if color.a < self.target { f32::min(self.target, color.a + step) } else { f32::max(self.target, color.a - step) };
+		
+		self.base_mut().set_color(color);
+		self.base_mut().set_visible(color.a > 0.0);
+	}
+}
+
+impl ScreenFade {
+	/// Fade to black over the given number of seconds.
+	pub fn fade_out(&mut self, duration: f32) { self.fade_to(1.0, duration); }
+	
+	/// Fade back from black over the given number of seconds.
+	pub fn fade_in(&mut self, duration: f32) { self.fade_to(0.0, duration); }
+	
+	/// Whether the last fade has finished.
+	pub fn is_done(&self) -> bool {
+		self.base().get_color().a == self.target
+	}
+	
+	/// Jump straight to black, without fading.
+	pub fn cover(&mut self) {
+		self.target = 1.0;
+		let mut color = self.base().get_color();
+		color.a = 1.0;
+		self.base_mut().set_color(color);
+		self.base_mut().show();
+	}
+	
+	fn fade_to(&mut self, target: f32, duration: f32) {
+		self.target = target;
+		self.speed = if duration > 0.0 { 1.0 / duration } else { f32::INFINITY };
 	}
 }
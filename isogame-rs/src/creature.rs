@@ -0,0 +1,462 @@
+use godot::prelude::*;
+use godot::classes::CharacterBody2D;
+use godot::classes::ICharacterBody2D;
+use godot::classes::AnimatedSprite2D;
+use godot::classes::SpriteFrames;
+
+use crate::error;
+use crate::error::GameError;
+use crate::error::GameResult;
+use crate::error::LineError;
+use crate::error::LogError;
+use crate::level;
+use crate::character::MovementState;
+use crate::character::MovementUpdate;
+use crate::character::MovingCharacter;
+use crate::faction::Attitude;
+use crate::faction::Faction;
+use crate::player::Player;
+use crate::spatial;
+use crate::util::PathfindingResult;
+
+/// How a creature behaves towards the other creatures around it.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Behaviour {
+	Hunter,		// Chases down anything its faction hunts, and attacks it
+	Prey,		// Runs away from anything its faction flees
+	#[default]
+	Idle
+}
+
+/// Everything that makes one kind of creature different from another.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CreatureDefinition {
+	pub id: String,
+	pub name: String,
+	pub sprites: String,		// Path to the SpriteFrames resource
+	pub walk: String,			// Animation used for moving, without the facing
+	pub attack: String,			// Animation used for attacking, without the facing
+	pub faction: Faction,
+	pub behaviour: Behaviour,
+	pub speed: f32,
+	pub health: f32,
+	pub damage: f32,
+	pub sight: i32,				// How many steps away the creature notices others from
+	pub loot: Option<String>,	// Loot table rolled when the creature dies
+	pub experience: u32			// Earned by whoever kills the creature
+}
+
+impl CreatureDefinition {
+	fn new(id: &str) -> Self {
+		Self {
+			id: id.to_string(),
+			name: id.to_string(),
+			sprites: String::new(),
+			walk: "walk".to_string(),
+			attack: "attack".to_string(),
+			faction: Faction::default(),
+			behaviour: Behaviour::default(),
+			speed: 1.0,
+			health: 100.0,
+			damage: 0.0,
+			sight: 5,
+			loot: None,
+			experience: 0
+		}
+	}
+}
+
+/// Every kind of creature in the game, parsed from a plain text file:
+///
+/// ```text
+/// [wolf]
+/// name: Wolf
+/// sprites: res://wolf/wolf_frames.tres
+/// attack: bite
+/// faction: Predators
+/// ai: hunter
+/// speed: 2.75
+/// health: 100
+/// damage: 5
+/// sight: 8
+/// loot: wolf
+/// experience: 40
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CreatureBook {
+	creatures: Vec<CreatureDefinition>
+}
+
+impl CreatureBook {
+	pub fn parse(source: &str) -> Result<Self, LineError> {
+		let creatures = error::parse_sections(source, "creature", "creature id", |id, _| Ok(CreatureDefinition::new(id)), |creature, line| {
+			let error = |message: &str| line.error(message);
+			let (key, value) = line.key_value()?;
+			let number = || value.parse::<f32>().map_err(|_| error("invalid number"));
+			
+			match key {
+				"name" => creature.name = value.to_string(),
+				"sprites" => creature.sprites = value.to_string(),
+				"walk" => creature.walk = value.to_string(),
+				"attack" => creature.attack = value.to_string(),
+				"faction" => creature.faction = Faction::from_name(value).ok_or_else(|| error("unknown faction"))?,
+				"ai" => creature.behaviour = match value {
+					"hunter" => Behaviour::Hunter,
+					"prey" => Behaviour::Prey,
+					"idle" => Behaviour::Idle,
+					_ => return Err(error("unknown ai"))
+				},
+				"speed" => creature.speed = number()?,
+				"health" => creature.health = number()?,
+				"damage" => creature.damage = number()?,
+				"sight" => creature.sight = value.parse().map_err(|_| error("invalid sight"))?,
+				"loot" => creature.loot = Some(value.to_string()),
+				"experience" => creature.experience = value.parse().map_err(|_| error("invalid experience"))?,
+				_ => return Err(error("unknown property"))
+			}
+			Ok(())
+		})?;
+		
+		Ok(Self { creatures })
+	}
+	
+	pub fn get(&self, id: &str) -> Option<&CreatureDefinition> {
+		self.creatures.iter().find(|creature| creature.id == id)
+	}
+}
+
+/// A creature whose stats, looks and behaviour all come from its definition, so that new kinds of creature can be
+/// added without touching any code. It does nothing until its level gives it a definition.
+#[derive(GodotClass)]
+#[class(base=CharacterBody2D)]
+pub struct Creature {
+	/// Which kind of creature this is, from the creature definitions file.
+	#[export]
+	pub kind: GString,
+	
+	pub definition: Option<CreatureDefinition>,
+	pub health: f32,
+	pub character: MovingCharacter,
+	attacker: Option<InstanceId>,		// Whoever hurt us last
+	pub input_delay: f64,
+	pub animation_state: CreatureAnimationState,
+	base: Base<CharacterBody2D>
+}
+
+#[godot_api]
+impl Creature {
+	#[signal]
+	pub fn update_nav(instance: Gd<Creature>);
+	/// Emitted when the creature dies, with its kind, the tile it was on and whoever dealt the killing blow, if they're still around.
+	#[signal]
+	pub fn dead(kind: GString, tile: Vector2i, killer: Option<Gd<Node>>);
+}
+
+#[godot_api]
+impl ICharacterBody2D for Creature {
+	fn init(base: Base<CharacterBody2D>) -> Self {
+		Self {
+			kind: GString::new(),
+			definition: None,
+			health: 0.0,
+			character: MovingCharacter::default(),
+			attacker: None,
+			input_delay: 0.00,
+			animation_state: CreatureAnimationState::Idle,
+			base
+		}
+	}
+	
+	fn ready(&mut self) {
+		// Add to the entities group.
+		self.base_mut().add_to_group("entities");
+	}
+	
+	fn physics_process(&mut self, delta: f64) {
+		let definition = match self.definition.clone() {
+			Some(x) => x,
+			None => return
+		};
+		
+		if !self.character.has_nav() {
+			// If we don't have pathfinding data, request it and wait.
+			self.ask_for_nav();
+			return;
+		}
+		
+		// Death logic. This comes first, so a dying creature never starts a step and reserves a tile it can't give back.
+		if self.health <= 0.00 {
+			let position = self.base().get_position();
+			let tile = self.character.occupied_tile(position);
+			self.character.leave(position);
+			self.base_mut().hide();
+			
+			let kind = self.kind.clone();
+			let killer = self.attacker.and_then(|attacker| Gd::try_from_instance_id(attacker).ok());
+			let mut sig = self.signals().dead();
+			sig.emit(&kind, tile, killer.as_ref());
+			
+			self.base_mut().queue_free();
+			return;
+		}
+		
+		// AI logic.
+		if self.input_delay > 0.00 {
+			self.input_delay -= delta;
+		} else if self.character.movement == MovementState::Idle {
+			match definition.behaviour {
+				Behaviour::Hunter => self.handle_hunting(),
+				Behaviour::Prey => self.handle_fleeing(),
+				Behaviour::Idle => ()
+			}
+		}
+		
+		// Movement logic.
+		let position = self.base().get_position();
+		match self.character.update_movement(position, definition.speed, delta) {
+			MovementUpdate::None if self.character.movement == MovementState::Busy => {
+				// Creatures are only ever busy attacking.
+				let sprite : Gd<AnimatedSprite2D> = self.base().get_node_as("AnimatedSprite2D");
+				
+				if sprite.get_animation().contains(definition.attack.as_str()) && !sprite.is_playing() {
+					// If the animation hasn't started yet (doesn't contain the attack's name), we must wait.
+					// If the animation hasn't finished yet (is still playing), we must wait.
+					
+					// If the target hasn't moved out of the way, we can damage them.
+					let facing_pos = self.character.calculate_movement_grid(self.base().get_position());
+					let targets : Vec<Gd<Node>> = match &self.character.index {
+						Some(index) => index.bind().at::<Node>(facing_pos).filter(|target| self.hunts(target)).collect(),
+						None => Vec::new()
+					};
+					let attacker = self.to_gd().upcast();
+					for target in targets {
+						attack(target, definition.damage, &attacker);
+					}
+					
+					// Whether or not we damaged our target, we are done attacking.
+					self.character.movement = MovementState::Idle;
+				}
+			},
+			MovementUpdate::None | MovementUpdate::Blocked => {
+				if self.input_delay <= 0.0 { self.animation_state = CreatureAnimationState::Idle; }
+			},
+			MovementUpdate::Started => self.animation_state = CreatureAnimationState::Walking,
+			MovementUpdate::Moved(position) | MovementUpdate::Arrived(position) => self.base_mut().set_position(position)
+		};
+		
+		// Animation logic.
+		let mut sprite : Gd<AnimatedSprite2D> = self.base().get_node_as("AnimatedSprite2D");
+		
+		let animation = match &self.animation_state {
+			CreatureAnimationState::Idle => "idle",
+			CreatureAnimationState::Walking => definition.walk.as_str(),
+			CreatureAnimationState::Attack => definition.attack.as_str()
+		};
+		sprite.set_animation(&self.character.facing.get_animation(animation));
+		if !sprite.is_playing() { sprite.play(); }
+	}
+}
+
+impl Creature {
+	/// Turn into the given kind of creature: take on its stats and faction, and load its sprites.
+	pub fn set_definition(&mut self, definition: CreatureDefinition) -> GameResult<()> {
+		let frames = try_load::<SpriteFrames>(&definition.sprites).map_err(|_| GameError::LoadFailed(definition.sprites.clone()))?;
+		let mut sprite : Gd<AnimatedSprite2D> = self.base().get_node_as("AnimatedSprite2D");
+		sprite.set_sprite_frames(&frames);
+		sprite.set_animation(&self.character.facing.get_animation("idle"));
+		sprite.play();
+		
+		let faction = definition.faction.group();
+		self.base_mut().add_to_group(faction);
+		self.health = definition.health;
+		self.definition = Some(definition);
+		Ok(())
+	}
+	
+	pub fn damage(&mut self, damage: f32, attacker: &Gd<Node>) {
+		self.health -= damage;
+		self.attacker = Some(attacker.instance_id());
+	}
+	
+	fn handle_hunting(&mut self) {
+		match self.find_path() {
+			PathfindingResult::NoPath => (), // If there is no path, do nothing.
+			PathfindingResult::ReachedTarget(target_tile) => {
+				// Only attack if we're actually facing the target.
+				let position = self.base().get_position();
+				if self.character.face_tile(position, target_tile).log_error("Creature couldn't face its target").is_none() { return; }
+				
+				self.character.movement = MovementState::Busy;
+				self.animation_state = CreatureAnimationState::Attack;
+			},
+			PathfindingResult::FoundPath(next_tile) => self.step_towards(next_tile, 0.2)
+		}
+	}
+	
+	fn handle_fleeing(&mut self) {
+		if let Some(next_tile) = self.find_escape() {
+			self.step_towards(next_tile, 0.1);
+		}
+	}
+	
+	/// Face a neighbouring tile, and start moving onto it if we were already facing it.
+	fn step_towards(&mut self, tile: Vector2i, delay: f64) {
+		let position = self.base().get_position();
+		let old_facing = self.character.facing.clone();
+		
+		if self.character.face_tile(position, tile).log_error("Creature couldn't follow its path").is_none() { return; }
+		
+		// Either change facing or move, but not both.
+		if self.character.facing == old_facing {
+			self.character.movement = MovementState::StartMoving;
+			self.animation_state = CreatureAnimationState::Walking;
+			self.input_delay = delay;
+		}
+	}
+	
+	/// Find the nearest creature close enough to notice that we feel a certain way about.
+	fn find_nearest(&self, attitude: Attitude) -> Option<Gd<Node2D>> {
+		let index = self.character.index.as_ref()?;
+		let definition = self.definition.as_ref()?;
+		let tile = self.character.get_gridpos(self.base().get_position());
+		index.bind().nearest(tile, definition.sight, |other| definition.faction.attitude_to(other) == attitude)
+	}
+	
+	fn hunts(&self, target: &Gd<Node>) -> bool {
+		let faction = match &self.definition {
+			Some(x) => x.faction,
+			None => return false
+		};
+		Faction::of(target).is_some_and(|other| faction.attitude_to(other) == Attitude::Hunt)
+	}
+	
+	/// Check whether we can find a path to nearby prey. Returns the next tile of the path.
+	fn find_path(&mut self) -> PathfindingResult {
+		// If we can't find a target, give up.
+		let target = match self.find_nearest(Attitude::Hunt) {
+			Some(x) => x,
+			None => return PathfindingResult::NoPath
+		};
+		
+		// Get the path origin and end.
+		let position = self.base().get_position();
+		let tilemap = self.character.tilemap.as_mut().unwrap();
+		let origin_pos = level::global_to_grid(tilemap, position);
+		let target_pos = level::global_to_grid(tilemap, target.get_position());
+		
+		// Check whether we already reached the target.
+		let neighbours = tilemap.get_surrounding_cells(origin_pos);
+		for neighbour in neighbours.iter_shared() {
+			if neighbour == target_pos { return PathfindingResult::ReachedTarget(neighbour); }
+		}
+		
+		// Get the four cells around the target.
+		let target_neighbours = tilemap.get_surrounding_cells(target_pos);
+		
+		let nav = self.character.nav.as_mut().unwrap();
+		for neighbour in target_neighbours.iter_shared() {
+			// Perform pathfinding.
+			let path = nav.get_id_path(origin_pos, neighbour);
+			// Check if a valid path was returned. If so, return index 1, which is the next tile.
+			if let Some(tile) = path.get(1) { return PathfindingResult::FoundPath(tile); }
+		}
+		
+		// If no valid paths we returned, give up.
+		PathfindingResult::NoPath
+	}
+	
+	/// If something we're afraid of is close by, find the open tile next to us that gets furthest away from it.
+	fn find_escape(&mut self) -> Option<Vector2i> {
+		let threat = self.find_nearest(Attitude::Flee)?;
+		let tile = self.character.get_gridpos(self.base().get_position());
+		let threat_tile = self.character.get_gridpos(threat.get_position());
+		
+		let tilemap = self.character.tilemap.as_mut().unwrap();
+		let nav = self.character.nav.as_ref().unwrap();
+		let neighbours = tilemap.get_surrounding_cells(tile);
+		
+		neighbours.iter_shared()
+			.filter(|neighbour| nav.is_in_boundsv(*neighbour) && !nav.is_point_solid(*neighbour))
+			.filter(|neighbour| spatial::distance(*neighbour, threat_tile) > spatial::distance(tile, threat_tile))
+			.max_by_key(|neighbour| spatial::distance(*neighbour, threat_tile))
+	}
+	
+	fn ask_for_nav(&mut self) {
+		let gd = self.to_gd();
+		let mut sig = self.signals().update_nav();
+		sig.emit(&gd);
+	}
+}
+
+/// Hurt whatever a creature has caught, if it can be hurt.
+fn attack(target: Gd<Node>, damage: f32, attacker: &Gd<Node>) {
+	if let Ok(mut player) = target.clone().try_cast::<Player>() {
+		player.bind_mut().damage(damage);
+	} else if let Ok(mut creature) = target.try_cast::<Creature>() {
+		creature.bind_mut().damage(damage, attacker);
+	}
+}
+
+pub enum CreatureAnimationState {
+	Idle,
+	Walking,
+	Attack
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	const SOURCE: &str = "
+		# Comments and blank lines are ignored.
+		[wolf]
+		name: Wolf
+		sprites: res://wolf/wolf_frames.tres
+		attack: bite
+		faction: Predators
+		ai: hunter
+		speed: 2.75
+		damage: 5
+		loot: wolf
+		experience: 40
+		
+		[rabbit]
+	";
+	
+	#[test]
+	fn parse() {
+		let book = CreatureBook::parse(SOURCE).unwrap();
+		let wolf = book.get("wolf").unwrap();
+		assert_eq!(wolf.name, "Wolf");
+		assert_eq!(wolf.attack, "bite");
+		assert_eq!(wolf.faction, Faction::Predators);
+		assert_eq!(wolf.behaviour, Behaviour::Hunter);
+		assert_eq!(wolf.speed, 2.75);
+		assert_eq!(wolf.loot.as_deref(), Some("wolf"));
+		assert_eq!(wolf.experience, 40);
+		assert!(book.get("stag").is_none());
+	}
+	
+	#[test]
+	fn defaults() {
+		let book = CreatureBook::parse(SOURCE).unwrap();
+		assert_eq!(*book.get("rabbit").unwrap(), CreatureDefinition::new("rabbit"));
+		
+		let wolf = book.get("wolf").unwrap();
+		assert_eq!(wolf.walk, "walk");
+		assert_eq!(wolf.health, 100.0);
+		assert_eq!(wolf.sight, 5);
+	}
+	
+	#[test]
+	fn errors() {
+		let error = |source: &str| CreatureBook::parse(source).unwrap_err();
+		assert_eq!(error("[wolf]\nhowl: loudly"), LineError::new(2, "unknown property"));
+		assert_eq!(error("[wolf]\nfaction: Wolves").message, "unknown faction");
+		assert_eq!(error("[wolf]\nfaction: predators").message, "unknown faction");
+		assert_eq!(error("[wolf]\nai: sleepy").message, "unknown ai");
+		assert_eq!(error("[wolf]\n[stag]\n[wolf]"), LineError::new(3, "duplicate creature id"));
+		assert_eq!(error("name: Wolf").message, "content before the first creature");
+		assert_eq!(error("[wolf]\nspeed: fast").message, "invalid number");
+	}
+}
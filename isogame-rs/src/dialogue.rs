@@ -0,0 +1,401 @@
+use std::collections::HashMap;
+
+use crate::error;
+use crate::error::LineError;
+use crate::error::SourceLine;
+
+/// A branching conversation, made up of named nodes. Conversations always begin at the "start" node.
+///
+/// Dialogue files are plain text, parsed line by line:
+///
+/// ```text
+/// # Comments start with a hash.
+/// [start]
+/// Innkeeper: Welcome to the Prancing Stag, traveller.
+/// ! set met_innkeeper
+/// * Any work going? -> work
+/// * I'd like a room. -> room if has Coin 3
+/// * Goodbye. -> end
+/// ```
+///
+/// - `[name]` begins a new node.
+/// - `Speaker: text` is a line of dialogue. Lines without a colon have no speaker.
+/// - `! action` is performed when the node is entered: `set flag`, `clear flag`, `give item [n]`, `take item [n]`
+///   or `start quest`.
+/// - `* text -> node` is a choice shown after the last line, optionally followed by `if` and comma-separated conditions:
+///   `has item [n]`, `flag name` or `not flag name`.
+/// - `-> node` continues to another node when there are no choices. Without it, the conversation ends.
+///
+/// The node name "end" always ends the conversation.
+#[derive(Clone, Debug, Default)]
+pub struct Dialogue {
+	nodes: HashMap<String, DialogueNode>
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DialogueNode {
+	pub lines: Vec<DialogueLine>,
+	pub actions: Vec<DialogueAction>,
+	pub choices: Vec<DialogueChoice>,
+	pub next: Option<String>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DialogueLine {
+	pub speaker: String,
+	pub text: String
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DialogueChoice {
+	pub text: String,
+	pub target: String,
+	pub conditions: Vec<DialogueCondition>
+}
+
+/// Something that happens to the game state when a dialogue node is entered.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DialogueAction {
+	SetFlag(String),
+	ClearFlag(String),
+	GiveItem(String, u32),
+	TakeItem(String, u32),
+	StartQuest(String)
+}
+
+/// A requirement that must be met for a choice to be shown.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DialogueCondition {
+	HasItem(String, u32),
+	Flag(String),
+	NotFlag(String)
+}
+
+/// The parts of the game state that dialogue conditions can inspect.
+pub trait DialogueContext {
+	fn has_item(&self, item: &str, quantity: u32) -> bool;
+	fn has_flag(&self, flag: &str) -> bool;
+}
+
+const START_NODE: &str = "start";
+const END_NODE: &str = "end";
+
+impl Dialogue {
+	pub fn parse(source: &str) -> Result<Self, LineError> {
+		// Each node is kept with its name and the line its header is on, so that broken links can be pointed out.
+		let new = |name: &str, line: &SourceLine| {
+			if name == END_NODE { return Err(line.error("invalid node name")); }
+			Ok((name.to_string(), line.number, DialogueNode::default()))
+		};
+		let sections = error::parse_sections(source, "node", "node name", new, |(_, _, node), line| {
+			let error = |message: &str| line.error(message);
+			let line = line.text;
+			
+			if let Some(rest) = line.strip_prefix('!') {
+				node.actions.push(parse_action(rest).ok_or_else(|| error("invalid action"))?);
+			} else if let Some(rest) = line.strip_prefix('*') {
+				node.choices.push(parse_choice(rest).ok_or_else(|| error("invalid choice"))?);
+			} else if let Some(rest) = line.strip_prefix("->") {
+				if node.next.is_some() { return Err(error("node already has a next node")); }
+				node.next = Some(rest.trim().to_string());
+			} else {
+				node.lines.push(parse_line(line));
+			}
+			Ok(())
+		})?;
+		
+		// Check the dialogue is complete and all the links point somewhere.
+		let exists = |name: &str| sections.iter().any(|(other, _, _)| other == name);
+		if !exists(START_NODE) {
+			return Err(LineError::new(0, "missing start node"));
+		}
+		
+		for (name, line_no, node) in sections.iter() {
+			let error = |message: String| LineError { line: *line_no, message };
+			
+			if node.lines.is_empty() { return Err(error(format!("node '{}' has no lines", name))); }
+			
+			let targets = node.choices.iter().map(|choice| &choice.target).chain(node.next.iter());
+			for target in targets {
+				if target != END_NODE && !exists(target) {
+					return Err(error(format!("node '{}' links to unknown node '{}'", name, target)));
+				}
+			}
+		}
+		
+		let nodes = sections.into_iter().map(|(name, _, node)| (name, node)).collect();
+		Ok(Self { nodes })
+	}
+	
+	pub fn get_node(&self, name: &str) -> Option<&DialogueNode> { self.nodes.get(name) }
+}
+
+fn parse_line(line: &str) -> DialogueLine {
+	match line.split_once(':') {
+		Some((speaker, text)) => DialogueLine { speaker: speaker.trim().to_string(), text: text.trim().to_string() },
+		None => DialogueLine { speaker: String::new(), text: line.to_string() }
+	}
+}
+
+fn parse_action(text: &str) -> Option<DialogueAction> {
+	let words : Vec<&str> = text.split_whitespace().collect();
+	match words.as_slice() {
+		["set", flag] => Some(DialogueAction::SetFlag(flag.to_string())),
+		["clear", flag] => Some(DialogueAction::ClearFlag(flag.to_string())),
+		["give", item] => Some(DialogueAction::GiveItem(item.to_string(), 1)),
+		["give", item, quantity] => Some(DialogueAction::GiveItem(item.to_string(), quantity.parse().ok()?)),
+		["take", item] => Some(DialogueAction::TakeItem(item.to_string(), 1)),
+		["take", item, quantity] => Some(DialogueAction::TakeItem(item.to_string(), quantity.parse().ok()?)),
+		["start", quest] => Some(DialogueAction::StartQuest(quest.to_string())),
+		_ => None
+	}
+}
+
+fn parse_condition(text: &str) -> Option<DialogueCondition> {
+	let words : Vec<&str> = text.split_whitespace().collect();
+	match words.as_slice() {
+		["has", item] => Some(DialogueCondition::HasItem(item.to_string(), 1)),
+		["has", item, quantity] => Some(DialogueCondition::HasItem(item.to_string(), quantity.parse().ok()?)),
+		["flag", flag] => Some(DialogueCondition::Flag(flag.to_string())),
+		["not", "flag", flag] => Some(DialogueCondition::NotFlag(flag.to_string())),
+		_ => None
+	}
+}
+
+fn parse_choice(text: &str) -> Option<DialogueChoice> {
+	let (text, rest) = text.split_once("->")?;
+	let (target, conditions) = match rest.split_once(" if ") {
+		Some((target, conditions)) => (target, Some(conditions)),
+		None => (rest, None)
+	};
+	
+	let conditions = match conditions {
+		Some(conditions) => conditions.split(',').map(parse_condition).collect::<Option<Vec<_>>>()?,
+		None => Vec::new()
+	};
+	
+	let text = text.trim();
+	let target = target.trim();
+	if text.is_empty() || target.is_empty() { return None; }
+	
+	Some(DialogueChoice { text: text.to_string(), target: target.to_string(), conditions })
+}
+
+impl DialogueCondition {
+	pub fn is_met(&self, context: &impl DialogueContext) -> bool {
+		match self {
+			DialogueCondition::HasItem(item, quantity) => context.has_item(item, *quantity),
+			DialogueCondition::Flag(flag) => context.has_flag(flag),
+			DialogueCondition::NotFlag(flag) => !context.has_flag(flag)
+		}
+	}
+}
+
+impl DialogueChoice {
+	pub fn is_available(&self, context: &impl DialogueContext) -> bool {
+		self.conditions.iter().all(|condition| condition.is_met(context))
+	}
+}
+
+/// Steps through a dialogue one line at a time. Any actions returned must be applied to the game state by the caller.
+pub struct DialogueRunner {
+	dialogue: Dialogue,
+	node: Option<String>,
+	line: usize
+}
+
+impl DialogueRunner {
+	pub fn new(dialogue: Dialogue) -> Self {
+		Self { dialogue, node: None, line: 0 }
+	}
+	
+	/// Begin the conversation from the start node.
+	pub fn start(&mut self) -> Vec<DialogueAction> { self.enter(START_NODE) }
+	
+	pub fn is_finished(&self) -> bool { self.node.is_none() }
+	
+	/// The line currently being displayed.
+	pub fn current_line(&self) -> Option<&DialogueLine> {
+		self.current_node()?.lines.get(self.line)
+	}
+	
+	/// The choices available on the current line, which are only offered after the last line of a node.
+	pub fn choices(&self, context: &impl DialogueContext) -> Vec<&DialogueChoice> {
+		match self.current_node() {
+			Some(node) if self.line + 1 == node.lines.len() => {
+				node.choices.iter().filter(|choice| choice.is_available(context)).collect()
+			},
+			_ => Vec::new()
+		}
+	}
+	
+	/// Move on from the current line. If choices are being offered, `choice` indexes into the list returned by `choices()`.
+	pub fn advance(&mut self, choice: Option<usize>, context: &impl DialogueContext) -> Vec<DialogueAction> {
+		let node = match self.current_node() {
+			Some(x) => x,
+			None => return Vec::new()
+		};
+		
+		// Move to the next line of the current node.
+		if self.line + 1 < node.lines.len() {
+			self.line += 1;
+			return Vec::new();
+		}
+		
+		// Otherwise, follow the chosen link, or the default one.
+		let choices = self.choices(context);
+		let has_choices = !choices.is_empty();
+		let target = if has_choices {
+			choice.and_then(|index| choices.get(index)).map(|choice| choice.target.clone())
+		} else {
+			node.next.clone()
+		};
+		
+		match target {
+			Some(target) => self.enter(&target),
+			None if !has_choices => {
+				self.node = None;
+				Vec::new()
+			},
+			None => Vec::new() // An invalid choice was made, so stay where we are.
+		}
+	}
+	
+	fn current_node(&self) -> Option<&DialogueNode> {
+		self.dialogue.get_node(self.node.as_ref()?)
+	}
+	
+	fn enter(&mut self, name: &str) -> Vec<DialogueAction> {
+		self.line = 0;
+		match self.dialogue.get_node(name) {
+			Some(node) => {
+				self.node = Some(name.to_string());
+				node.actions.clone()
+			},
+			None => {
+				self.node = None;
+				Vec::new()
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	struct Context {
+		items: Vec<(&'static str, u32)>,
+		flags: Vec<&'static str>
+	}
+	
+	impl DialogueContext for Context {
+		fn has_item(&self, item: &str, quantity: u32) -> bool {
+			self.items.iter().any(|(other, count)| *other == item && *count >= quantity)
+		}
+		
+		fn has_flag(&self, flag: &str) -> bool {
+			self.flags.contains(&flag)
+		}
+	}
+	
+	const EMPTY: Context = Context { items: Vec::new(), flags: Vec::new() };
+	
+	const SOURCE: &str = "
+		[start]
+		Innkeeper: Welcome, traveller.
+		Innkeeper: What can I do for you?
+		! set met_innkeeper
+		* Any work going? -> work
+		* I'd like a room. -> room if has Coin 3
+		* Goodbye. -> end
+		
+		[work]
+		Innkeeper: Wolves have been taking the sheep.
+		! start wolf_hunt
+		-> start
+		
+		[room]
+		Innkeeper: Sleep well.
+	";
+	
+	#[test]
+	fn unknown_link() {
+		let error = Dialogue::parse("[start]\nHello.\n-> nowhere").unwrap_err();
+		assert_eq!(error.line, 1);
+		assert!(error.message.contains("unknown node 'nowhere'"));
+	}
+	
+	#[test]
+	fn missing_start_node() {
+		let error = Dialogue::parse("[middle]\nHello.").unwrap_err();
+		assert_eq!(error, LineError::new(0, "missing start node"));
+	}
+	
+	#[test]
+	fn duplicate_node() {
+		let error = Dialogue::parse("[start]\nHello.\n[start]\nHello again.").unwrap_err();
+		assert_eq!(error, LineError::new(3, "duplicate node name"));
+	}
+	
+	#[test]
+	fn choice_conditions() {
+		let choice = parse_choice(" Open it. -> open if has Key, flag unlocked, not flag broken").unwrap();
+		assert_eq!(choice.target, "open");
+		
+		let mut context = Context { items: vec![("Key", 1)], flags: vec!["unlocked"] };
+		assert!(choice.is_available(&context));
+		
+		context.flags.push("broken");
+		assert!(!choice.is_available(&context));
+		assert!(!choice.is_available(&EMPTY));
+		
+		let coins = DialogueCondition::HasItem("Coin".to_string(), 3);
+		assert!(!coins.is_met(&Context { items: vec![("Coin", 2)], flags: Vec::new() }));
+		assert!(coins.is_met(&Context { items: vec![("Coin", 3)], flags: Vec::new() }));
+	}
+	
+	#[test]
+	fn advance_and_next() {
+		let mut runner = DialogueRunner::new(Dialogue::parse(SOURCE).unwrap());
+		assert_eq!(runner.start(), vec![DialogueAction::SetFlag("met_innkeeper".to_string())]);
+		assert_eq!(runner.current_line().unwrap().text, "Welcome, traveller.");
+		
+		// Choices are only offered on the last line, and unavailable ones are left out.
+		assert!(runner.choices(&EMPTY).is_empty());
+		assert!(runner.advance(None, &EMPTY).is_empty());
+		let choices : Vec<&str> = runner.choices(&EMPTY).iter().map(|choice| choice.target.as_str()).collect();
+		assert_eq!(choices, ["work", "end"]);
+		
+		// Following a choice performs the new node's actions, then "->" leads back to the start.
+		assert_eq!(runner.advance(Some(0), &EMPTY), vec![DialogueAction::StartQuest("wolf_hunt".to_string())]);
+		assert_eq!(runner.current_line().unwrap().text, "Wolves have been taking the sheep.");
+		runner.advance(None, &EMPTY);
+		assert_eq!(runner.current_line().unwrap().text, "Welcome, traveller.");
+		
+		// A node with no choices and no next node ends the conversation.
+		let rich = Context { items: vec![("Coin", 5)], flags: Vec::new() };
+		runner.advance(None, &rich);
+		runner.advance(Some(1), &rich);
+		assert_eq!(runner.current_line().unwrap().text, "Sleep well.");
+		runner.advance(None, &rich);
+		assert!(runner.is_finished());
+		assert!(runner.current_line().is_none());
+	}
+	
+	#[test]
+	fn invalid_choice_stays() {
+		let mut runner = DialogueRunner::new(Dialogue::parse(SOURCE).unwrap());
+		runner.start();
+		runner.advance(None, &EMPTY);
+		
+		assert!(runner.advance(None, &EMPTY).is_empty());
+		assert!(runner.advance(Some(5), &EMPTY).is_empty());
+		assert!(!runner.is_finished());
+		assert_eq!(runner.current_line().unwrap().text, "What can I do for you?");
+		
+		// Choosing "end" finishes the conversation.
+		runner.advance(Some(1), &EMPTY);
+		assert!(runner.is_finished());
+	}
+}
@@ -0,0 +1,209 @@
+use std::fmt;
+
+use godot::prelude::*;
+use godot::classes::PackedScene;
+
+use crate::reservation::ReservationConflict;
+
+/// Everything that can go wrong in the game which shouldn't crash it, usually because a scene or data file was authored incorrectly.
+#[derive(Debug)]
+pub enum GameError {
+	MissingNode(String),						// Path to the node
+	WrongType(String, String),					// Path to the node or resource, and the class it should have been
+	LoadFailed(String),							// Path to the resource
+	MissingTileSet(String),						// Name of the tilemap layer
+	MissingSpawnPoint(String, String),			// Path to the level and name of the spawn point
+	NotAdjacent(Vector2i, Vector2i),			// Tile we're on and the tile we tried to face
+	OutOfRange(f64, f64, f64),					// Value, minimum and maximum
+	GenerationFailed(i64),						// Seed of the level that couldn't be generated
+	Dialogue(String, LineError),				// Path to the dialogue file
+	Quest(String, LineError),					// Path to the quest file
+	Creature(String, LineError),				// Path to the creature file
+	UnknownCreature(String),					// Kind of creature that isn't in the creature file
+	Loot(String, LineError),					// Path to the loot file
+	UnknownLootTable(String),					// Loot table that isn't in the loot file
+	Replay(String, LineError),					// Path to the recording
+	Reservation(Vector2i, ReservationConflict)	// Tile that couldn't be reserved
+}
+
+pub type GameResult<T> = Result<T, GameError>;
+
+impl fmt::Display for GameError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			GameError::MissingNode(path) => write!(f, "missing node '{}'", path),
+			GameError::WrongType(path, class) => write!(f, "'{}' should be a {}", path, class),
+			GameError::LoadFailed(path) => write!(f, "could not load {}", path),
+			GameError::MissingTileSet(layer) => write!(f, "{} has no tile set", layer),
+			GameError::MissingSpawnPoint(level, spawn) => write!(f, "{} has no spawn point named '{}'", level, spawn),
+			GameError::NotAdjacent(from, to) => write!(f, "tile {} is not adjacent to {}", to, from),
+			GameError::OutOfRange(value, min, max) => write!(f, "{} is outside the range {} to {}", value, min, max),
+			GameError::GenerationFailed(seed) => write!(f, "the level generated from seed {} has no floor", seed),
+			GameError::Dialogue(path, e) => write!(f, "invalid dialogue in {}: {}", path, e),
+			GameError::Quest(path, e) => write!(f, "invalid quests in {}: {}", path, e),
+			GameError::Creature(path, e) => write!(f, "invalid creatures in {}: {}", path, e),
+			GameError::UnknownCreature(kind) => write!(f, "there is no creature called '{}'", kind),
+			GameError::Loot(path, e) => write!(f, "invalid loot tables in {}: {}", path, e),
+			GameError::UnknownLootTable(id) => write!(f, "there is no loot table called '{}'", id),
+			GameError::Replay(path, e) => write!(f, "invalid recording in {}: {}", path, e),
+			GameError::Reservation(tile, e) => write!(f, "tile {} can't be reserved, because {}", tile, e)
+		}
+	}
+}
+
+impl std::error::Error for GameError {}
+
+/// Log an error through Godot, rather than crashing, along with what we were trying to do.
+pub trait LogError<T> {
+	fn log_error(self, context: &str) -> Option<T>;
+}
+
+impl<T> LogError<T> for GameResult<T> {
+	fn log_error(self, context: &str) -> Option<T> {
+		match self {
+			Ok(x) => Some(x),
+			Err(e) => {
+				godot_error!("{}: {}", context, e);
+				None
+			}
+		}
+	}
+}
+
+/// Fetch a child node of the right class, or say which one is missing.
+pub fn find_node<T>(parent: &Node, path: &str) -> GameResult<Gd<T>>
+where
+	T: GodotClass + Inherits<Node>
+{
+	let node = parent.get_node_or_null(path).ok_or_else(|| GameError::MissingNode(path.to_string()))?;
+	node.try_cast().map_err(|_| GameError::WrongType(path.to_string(), T::class_name().to_string()))
+}
+
+/// Create an instance of a scene, checking that its root node has the right class.
+pub fn instantiate<T>(scene: &Gd<PackedScene>, path: &GString) -> GameResult<Gd<T>>
+where
+	T: GodotClass + Inherits<Node>
+{
+	let node = scene.instantiate().ok_or_else(|| GameError::LoadFailed(path.to_string()))?;
+	node.try_cast().map_err(|node| {
+		node.free();
+		GameError::WrongType(path.to_string(), T::class_name().to_string())
+	})
+}
+
+/// A mistake in one of the game's plain text files, and the line it's on. Line 0 means the file as a whole.
+#[derive(Debug, PartialEq)]
+pub struct LineError {
+	pub line: usize,
+	pub message: String
+}
+
+impl LineError {
+	pub fn new(line: usize, message: &str) -> Self {
+		Self { line, message: message.to_string() }
+	}
+}
+
+impl fmt::Display for LineError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "line {}: {}", self.line, self.message)
+	}
+}
+
+impl std::error::Error for LineError {}
+
+/// A line of a plain text file with something on it, trimmed, along with its line number.
+pub struct SourceLine<'a> {
+	pub number: usize,
+	pub text: &'a str
+}
+
+impl<'a> SourceLine<'a> {
+	pub fn error(&self, message: &str) -> LineError {
+		LineError::new(self.number, message)
+	}
+	
+	/// Split a `key: value` line, trimming both halves.
+	pub fn key_value(&self) -> Result<(&'a str, &'a str), LineError> {
+		let (key, value) = self.text.split_once(':').ok_or_else(|| self.error("expected 'key: value'"))?;
+		Ok((key.trim(), value.trim()))
+	}
+}
+
+/// Every line of a plain text file with something on it. Blank lines, and comments starting with a hash, are skipped.
+pub fn source_lines(source: &str) -> impl Iterator<Item = SourceLine<'_>> {
+	source.lines().enumerate()
+		.map(|(index, raw)| SourceLine { number: index + 1, text: raw.trim() })
+		.filter(|line| !line.text.is_empty() && !line.text.starts_with('#'))
+}
+
+/// Parse a plain text file made of sections, each starting with a header like `[wolf]`. `new` starts a section from its
+/// id, and `add` adds each line after the header to it. Ids must be unique, and nothing can come before the first header.
+/// `section` and `id` name what the sections and their ids are called, for error messages.
+pub fn parse_sections<T>(
+	source: &str,
+	section: &str,
+	id: &str,
+	mut new: impl FnMut(&str, &SourceLine) -> Result<T, LineError>,
+	mut add: impl FnMut(&mut T, &SourceLine) -> Result<(), LineError>
+) -> Result<Vec<T>, LineError> {
+	let mut sections : Vec<T> = Vec::new();
+	let mut ids : Vec<String> = Vec::new();
+	
+	for line in source_lines(source) {
+		if let Some(rest) = line.text.strip_prefix('[') {
+			let name = rest.strip_suffix(']').ok_or_else(|| line.error(&format!("unterminated {}", id)))?.trim();
+			if name.is_empty() { return Err(line.error(&format!("invalid {}", id))); }
+			if ids.iter().any(|other| other == name) { return Err(line.error(&format!("duplicate {}", id))); }
+			
+			sections.push(new(name, &line)?);
+			ids.push(name.to_string());
+			continue;
+		}
+		
+		let current = sections.last_mut().ok_or_else(|| line.error(&format!("content before the first {}", section)))?;
+		add(current, &line)?;
+	}
+	
+	Ok(sections)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn parse(source: &str) -> Result<Vec<(String, Vec<String>)>, LineError> {
+		parse_sections(source, "thing", "thing id",
+			|id, _| Ok((id.to_string(), Vec::new())),
+			|(_, lines), line| {
+				lines.push(line.text.to_string());
+				Ok(())
+			})
+	}
+	
+	#[test]
+	fn sections() {
+		let sections = parse("# A comment.\n[a]\n  one  \n\n[b]\ntwo\nthree").unwrap();
+		assert_eq!(sections, vec![
+			("a".to_string(), vec!["one".to_string()]),
+			("b".to_string(), vec!["two".to_string(), "three".to_string()])
+		]);
+	}
+	
+	#[test]
+	fn section_errors() {
+		assert_eq!(parse("one\n[a]").unwrap_err(), LineError::new(1, "content before the first thing"));
+		assert_eq!(parse("[a\none").unwrap_err(), LineError::new(1, "unterminated thing id"));
+		assert_eq!(parse("[ ]").unwrap_err(), LineError::new(1, "invalid thing id"));
+		assert_eq!(parse("[a]\n[b]\n\n[a]").unwrap_err(), LineError::new(4, "duplicate thing id"));
+	}
+	
+	#[test]
+	fn key_value() {
+		let line = SourceLine { number: 3, text: "name : Big Wolf" };
+		assert_eq!(line.key_value().unwrap(), ("name", "Big Wolf"));
+		
+		let line = SourceLine { number: 3, text: "name" };
+		assert_eq!(line.key_value().unwrap_err(), LineError::new(3, "expected 'key: value'"));
+	}
+}
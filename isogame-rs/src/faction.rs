@@ -0,0 +1,67 @@
+use godot::prelude::*;
+
+/// Which side a creature is on, which decides who it hunts, who it runs from and who it ignores.
+/// Every member of a faction is in the faction's group, so it can be told apart from anything else in the level.
+#[derive(GodotConvert, Var, Export, Clone, Copy, Default, Debug, PartialEq, Eq, Hash)]
+#[godot(via = GString)]
+pub enum Faction {
+	Player,
+	Wildlife,
+	Predators,
+	#[default]
+	Townsfolk
+}
+
+/// How a member of one faction behaves towards a member of another.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Attitude {
+	Ignore,
+	Hunt,
+	Flee
+}
+
+/// How each faction (the rows) behaves towards each other faction (the columns), in the order they're declared.
+const HOSTILITY: [[Attitude; 4]; 4] = {
+	use Attitude::*;
+	[
+		//  Player  Wildlife  Predators  Townsfolk
+		[Ignore, Ignore, Ignore, Ignore],		// Player
+		[Ignore, Ignore, Flee, Ignore],			// Wildlife
+		[Hunt, Hunt, Ignore, Ignore],			// Predators
+		[Ignore, Ignore, Ignore, Ignore]		// Townsfolk
+	]
+};
+
+impl Faction {
+	pub const ALL: [Faction; 4] = [Faction::Player, Faction::Wildlife, Faction::Predators, Faction::Townsfolk];
+	
+	/// Name of the group every member of the faction is in.
+	pub fn group(&self) -> &'static str {
+		match self {
+			Faction::Player => "faction_player",
+			Faction::Wildlife => "faction_wildlife",
+			Faction::Predators => "faction_predators",
+			Faction::Townsfolk => "faction_townsfolk"
+		}
+	}
+	
+	/// The faction with this name, as it's written in data files like `Predators`.
+	pub fn from_name(name: &str) -> Option<Faction> {
+		match name {
+			"Player" => Some(Faction::Player),
+			"Wildlife" => Some(Faction::Wildlife),
+			"Predators" => Some(Faction::Predators),
+			"Townsfolk" => Some(Faction::Townsfolk),
+			_ => None
+		}
+	}
+	
+	/// Which faction a node belongs to, if any.
+	pub fn of(node: &Gd<Node>) -> Option<Faction> {
+		Self::ALL.into_iter().find(|faction| node.is_in_group(faction.group()))
+	}
+	
+	pub fn attitude_to(&self, other: Faction) -> Attitude {
+		HOSTILITY[*self as usize][other as usize]
+	}
+}
@@ -0,0 +1,495 @@
+use std::collections::VecDeque;
+
+use godot::prelude::*;
+use godot::classes::Node2D;
+use godot::classes::PackedScene;
+use godot::classes::TileMapLayer;
+
+use crate::error;
+use crate::error::GameError;
+use crate::error::GameResult;
+use crate::level;
+use crate::level::SpawnPoint;
+use crate::level_manager::LevelWarp;
+use crate::rng;
+use crate::rng::GameRng;
+use crate::rng::Rng;
+
+/// The different ways of laying out a generated level.
+#[derive(GodotConvert, Var, Export, Clone, Copy, Default, Debug, PartialEq)]
+#[godot(via = GString)]
+pub enum GenerationAlgorithm {
+	/// Rectangular rooms joined by corridors.
+	#[default]
+	Rooms,
+	/// Organic caves grown with a cellular automaton.
+	Caves
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cell {
+	Empty,
+	Floor,
+	Wall
+}
+
+/// A grid of floors and walls, with (0, 0) in the top corner.
+#[derive(Clone, Debug)]
+pub struct LevelLayout {
+	pub width: i32,
+	pub height: i32,
+	cells: Vec<Cell>
+}
+
+impl LevelLayout {
+	fn new(width: i32, height: i32) -> Self {
+		let width = width.max(1);
+		let height = height.max(1);
+		Self { width, height, cells: vec![Cell::Empty; (width * height) as usize] }
+	}
+	
+	/// The cell at a position. Anything outside the layout is empty.
+	pub fn get(&self, x: i32, y: i32) -> Cell {
+		if x < 0 || y < 0 || x >= self.width || y >= self.height { return Cell::Empty; }
+		self.cells[(y * self.width + x) as usize]
+	}
+	
+	fn set(&mut self, x: i32, y: i32, cell: Cell) {
+		if x < 0 || y < 0 || x >= self.width || y >= self.height { return; }
+		self.cells[(y * self.width + x) as usize] = cell;
+	}
+	
+	pub fn is_floor(&self, x: i32, y: i32) -> bool { self.get(x, y) == Cell::Floor }
+	
+	/// Every floor cell, in order from the top corner.
+	pub fn floors(&self) -> Vec<(i32, i32)> {
+		self.cells().filter(|(_, _, cell)| *cell == Cell::Floor).map(|(x, y, _)| (x, y)).collect()
+	}
+	
+	/// Every cell and its position, in order from the top corner.
+	pub fn cells(&self) -> impl Iterator<Item = (i32, i32, Cell)> + '_ {
+		self.cells.iter().enumerate().map(|(i, cell)| (i as i32 % self.width, i as i32 / self.width, *cell))
+	}
+	
+	/// Surround every floor with walls, including diagonally, so the level is closed off.
+	fn add_walls(&mut self) {
+		for y in 0..self.height {
+			for x in 0..self.width {
+				if self.get(x, y) != Cell::Empty { continue; }
+				let next_to_floor = NEIGHBOURS_8.iter().any(|(dx, dy)| self.is_floor(x + dx, y + dy));
+				if next_to_floor { self.set(x, y, Cell::Wall); }
+			}
+		}
+	}
+	
+	/// How many steps it takes to walk from a floor to every other floor, or `None` where it can't be reached.
+	/// Cells in `blocked` can't be walked through.
+	pub fn distances(&self, from: (i32, i32), blocked: &[(i32, i32)]) -> Vec<Option<u32>> {
+		let mut distances = vec![None; self.cells.len()];
+		if !self.is_floor(from.0, from.1) { return distances; }
+		
+		let mut queue = VecDeque::new();
+		distances[(from.1 * self.width + from.0) as usize] = Some(0);
+		queue.push_back((from, 0));
+		
+		while let Some(((x, y), distance)) = queue.pop_front() {
+			for (dx, dy) in NEIGHBOURS_4.iter() {
+				let (nx, ny) = (x + dx, y + dy);
+				if !self.is_floor(nx, ny) || blocked.contains(&(nx, ny)) { continue; }
+				
+				let index = (ny * self.width + nx) as usize;
+				if distances[index].is_some() { continue; }
+				distances[index] = Some(distance + 1);
+				queue.push_back(((nx, ny), distance + 1));
+			}
+		}
+		distances
+	}
+	
+	fn distance(&self, distances: &[Option<u32>], x: i32, y: i32) -> Option<u32> {
+		distances[(y * self.width + x) as usize]
+	}
+}
+
+/// The four directions characters can move in. On a diamond-shaped isometric tilemap, these are the four diagonals on screen.
+const NEIGHBOURS_4: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+const NEIGHBOURS_8: [(i32, i32); 8] = [(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+#[derive(Clone, Copy)]
+struct Room {
+	x: i32,
+	y: i32,
+	width: i32,
+	height: i32
+}
+
+impl Room {
+	fn centre(&self) -> (i32, i32) { (self.x + self.width / 2, self.y + self.height / 2) }
+	
+	/// Whether two rooms overlap or touch, since rooms need at least one wall between them.
+	fn touches(&self, other: &Room) -> bool {
+		self.x <= other.x + other.width && other.x <= self.x + self.width &&
+		self.y <= other.y + other.height && other.y <= self.y + self.height
+	}
+}
+
+/// Lay out rectangular rooms at random, each joined to the previous one by an L-shaped corridor.
+pub fn rooms_and_corridors(width: i32, height: i32, rng: &mut Rng) -> LevelLayout {
+	const MAX_ROOMS: usize = 8;
+	const ATTEMPTS: usize = 40;
+	
+	let mut layout = LevelLayout::new(width, height);
+	let mut rooms : Vec<Room> = Vec::new();
+	
+	for _ in 0..ATTEMPTS {
+		if rooms.len() >= MAX_ROOMS { break; }
+		
+		// Leave a border around the edge for the walls.
+		let room_width = rng.range(3, 6).min(width - 2);
+		let room_height = rng.range(3, 6).min(height - 2);
+		let room = Room {
+			x: rng.range(1, width - room_width - 1),
+			y: rng.range(1, height - room_height - 1),
+			width: room_width,
+			height: room_height
+		};
+		if rooms.iter().any(|other| room.touches(other)) { continue; }
+		
+		for y in room.y..room.y + room.height {
+			for x in room.x..room.x + room.width {
+				layout.set(x, y, Cell::Floor);
+			}
+		}
+		
+		if let Some(previous) = rooms.last() {
+			carve_corridor(&mut layout, previous.centre(), room.centre(), rng.chance(0.5));
+		}
+		rooms.push(room);
+	}
+	
+	layout.add_walls();
+	layout
+}
+
+fn carve_corridor(layout: &mut LevelLayout, from: (i32, i32), to: (i32, i32), x_first: bool) {
+	let corner = if x_first { (to.0, from.1) } else { (from.0, to.1) };
+	
+	for (start, end) in [(from, corner), (corner, to)] {
+		for x in start.0.min(end.0)..=start.0.max(end.0) {
+			for y in start.1.min(end.1)..=start.1.max(end.1) {
+				layout.set(x, y, Cell::Floor);
+			}
+		}
+	}
+}
+
+/// Grow caves by filling the level with random noise, then smoothing it out. Only the largest cave is kept, so every floor can be reached.
+pub fn caves(width: i32, height: i32, rng: &mut Rng) -> LevelLayout {
+	const WALL_CHANCE: f64 = 0.45;
+	const ITERATIONS: usize = 4;
+	
+	let mut layout = LevelLayout::new(width, height);
+	let is_border = |x: i32, y: i32| x == 0 || y == 0 || x == width - 1 || y == height - 1;
+	
+	for y in 0..height {
+		for x in 0..width {
+			if !is_border(x, y) && !rng.chance(WALL_CHANCE) { layout.set(x, y, Cell::Floor); }
+		}
+	}
+	
+	// Cells surrounded by rock become rock, and cells surrounded by space become space.
+	for _ in 0..ITERATIONS {
+		let previous = layout.clone();
+		for y in 0..height {
+			for x in 0..width {
+				let rock = NEIGHBOURS_8.iter().filter(|(dx, dy)| !previous.is_floor(x + dx, y + dy)).count();
+				let cell = if is_border(x, y) || rock >= 5 { Cell::Empty } else { Cell::Floor };
+				layout.set(x, y, cell);
+			}
+		}
+	}
+	
+	// Fill in every cave except the largest.
+	let mut largest : Vec<Option<u32>> = Vec::new();
+	let mut largest_size = 0;
+	let mut visited = vec![false; layout.cells.len()];
+	for (x, y) in layout.floors() {
+		if visited[(y * width + x) as usize] { continue; }
+		
+		let distances = layout.distances((x, y), &[]);
+		let size = distances.iter().filter(|distance| distance.is_some()).count();
+		for (index, distance) in distances.iter().enumerate() {
+			if distance.is_some() { visited[index] = true; }
+		}
+		if size > largest_size {
+			largest_size = size;
+			largest = distances;
+		}
+	}
+	for (x, y) in layout.floors() {
+		if layout.distance(&largest, x, y).is_none() { layout.set(x, y, Cell::Empty); }
+	}
+	
+	layout.add_walls();
+	layout
+}
+
+/// Where things go in a generated level, in layout coordinates.
+#[derive(Clone, Debug, Default)]
+pub struct Placement {
+	pub start: (i32, i32),
+	pub exit: (i32, i32),
+	pub wolves: Vec<(i32, i32)>,
+	pub scenery: Vec<(i32, i32)>
+}
+
+/// Creatures are never placed this close to the start, in steps.
+const SAFE_DISTANCE: u32 = 4;
+
+/// Choose a start, an exit as far away from it as possible, and places for creatures and scenery.
+/// Scenery is never placed where it would cut off part of the level.
+pub fn place(layout: &LevelLayout, rng: &mut Rng, wolves: usize, scenery: usize) -> Option<Placement> {
+	let floors = layout.floors();
+	let start = *rng.pick(&floors)?;
+	
+	let distances = layout.distances(start, &[]);
+	let exit = floors.iter()
+		.copied()
+		.max_by_key(|(x, y)| layout.distance(&distances, *x, *y).unwrap_or(0))?;
+	
+	let mut candidates : Vec<(i32, i32)> = floors.iter()
+		.copied()
+		.filter(|&(x, y)| (x, y) != exit && layout.distance(&distances, x, y).is_some_and(|distance| distance >= SAFE_DISTANCE))
+		.collect();
+	rng.shuffle(&mut candidates);
+	
+	let mut placement = Placement { start, exit, ..Placement::default() };
+	let reachable = distances.iter().filter(|distance| distance.is_some()).count();
+	
+	for tile in candidates {
+		if placement.wolves.len() < wolves {
+			placement.wolves.push(tile);
+		} else if placement.scenery.len() < scenery {
+			let mut blocked = placement.scenery.clone();
+			blocked.push(tile);
+			let still_reachable = layout.distances(start, &blocked).iter().filter(|distance| distance.is_some()).count();
+			if still_reachable + blocked.len() == reachable { placement.scenery = blocked; }
+		} else {
+			break;
+		}
+	}
+	
+	Some(placement)
+}
+
+/// Fills in the level it belongs to when the level is loaded, replacing whatever was in its `TerrainLayer` and `ForegroundLayer`.
+/// The level is drawn from the game's random seed and the generator's own `seed`, so the same pair always generates the same level.
+///
+/// The player arrives at a spawn point named by `spawn_name`, and leaves through a warp placed as far away from it as possible.
+#[derive(GodotClass)]
+#[class(base=Node,init)]
+pub struct LevelGenerator {
+	#[export]
+	algorithm: GenerationAlgorithm,
+	
+	/// Tells generated levels apart within a run. Levels with the same seed are laid out the same.
+	#[export]
+	seed: i64,
+	
+	/// Top corner of the generated area, in tiles. The level must fit inside the navigation grid, which is the size of the viewport.
+	#[export]
+	origin: Vector2i,
+	
+	#[export]
+	#[init(val = Vector2i::new(14, 24))]
+	size: Vector2i,
+	
+	/// Tileset source and atlas coordinates of the floor tiles, chosen between at random.
+	#[export]
+	floor_source: i32,
+	
+	#[export]
+	#[init(val = tavern_floor_tiles())]
+	floor_tiles: Array<Vector2i>,
+	
+	#[export]
+	#[init(val = 5)]
+	wall_source: i32,
+	
+	#[export]
+	wall_tile: Vector2i,
+	
+	#[export]
+	wolf: Option<Gd<PackedScene>>,
+	
+	#[export]
+	#[init(val = 3)]
+	wolves: i32,
+	
+	#[export]
+	scenery: Option<Gd<PackedScene>>,
+	
+	#[export]
+	#[init(val = 4)]
+	scenery_count: i32,
+	
+	#[export]
+	warp: Option<Gd<PackedScene>>,
+	
+	/// Level and spawn point that the exit warp leads to.
+	#[export]
+	warp_level: GString,
+	
+	#[export]
+	warp_spawn: GString,
+	
+	#[export]
+	#[init(val = GString::from("Start"))]
+	spawn_name: GString,
+	
+	base: Base<Node>
+}
+
+/// All sixteen tavern floor tiles.
+fn tavern_floor_tiles() -> Array<Vector2i> {
+	(0..16).map(|i| Vector2i::new(i % 4, i / 4)).collect()
+}
+
+impl LevelGenerator {
+	/// Fill in a level's tilemap layers and add its entities. This works before the level enters the scene tree.
+	pub fn generate(&self, mut level: Gd<Node2D>, rng: &mut GameRng) -> GameResult<()> {
+		// A copy of the substream, so the level comes out the same every time it's visited.
+		let mut rng = rng.stream(&format!("{}/{}", rng::stream::GENERATION, self.seed)).clone();
+		let layout = match self.algorithm {
+			GenerationAlgorithm::Rooms => rooms_and_corridors(self.size.x, self.size.y, &mut rng),
+			GenerationAlgorithm::Caves => caves(self.size.x, self.size.y, &mut rng)
+		};
+		let placement = place(&layout, &mut rng, self.wolves.max(0) as usize, self.scenery_count.max(0) as usize)
+			.ok_or(GameError::GenerationFailed(self.seed))?;
+		
+		let mut terrain : Gd<TileMapLayer> = error::find_node(&level, "TerrainLayer")?;
+		let mut foreground : Gd<TileMapLayer> = error::find_node(&level, "ForegroundLayer")?;
+		self.fill_tiles(&layout, &mut terrain, &mut foreground, &mut rng);
+		
+		let position = |(x, y): (i32, i32)| level::grid_to_level(&terrain, self.origin + Vector2i::new(x, y));
+		
+		let mut spawn_point = SpawnPoint::new_alloc();
+		spawn_point.set_name(&self.spawn_name);
+		spawn_point.set_position(position(placement.start));
+		level.add_child(&spawn_point);
+		
+		if let Some(scene) = self.warp.as_ref() {
+			let mut warp : Gd<LevelWarp> = error::instantiate(scene, &scene.get_path())?;
+			warp.set_name("Exit");
+			warp.set_position(position(placement.exit));
+			warp.bind_mut().set_level(self.warp_level.clone());
+			warp.bind_mut().set_spawn(self.warp_spawn.clone());
+			warp.add_to_group_ex("warps").persistent(true).done();
+			level.add_child(&warp);
+		}
+		
+		if let Some(scene) = self.wolf.as_ref() {
+			for (i, tile) in placement.wolves.iter().enumerate() {
+				let mut wolf : Gd<Node2D> = error::instantiate(scene, &scene.get_path())?;
+				wolf.set_name(&format!("Wolf{}", i + 1));
+				wolf.set_position(position(*tile));
+				level.add_child(&wolf);
+			}
+		}
+		
+		if let Some(scene) = self.scenery.as_ref() {
+			for (i, tile) in placement.scenery.iter().enumerate() {
+				let mut scenery : Gd<Node2D> = error::instantiate(scene, &scene.get_path())?;
+				scenery.set_name(&format!("Scenery{}", i + 1));
+				scenery.set_position(position(*tile));
+				scenery.add_to_group_ex("scenery").persistent(true).done();
+				level.add_child(&scenery);
+			}
+		}
+		
+		Ok(())
+	}
+	
+	fn fill_tiles(&self, layout: &LevelLayout, terrain: &mut Gd<TileMapLayer>, foreground: &mut Gd<TileMapLayer>, rng: &mut Rng) {
+		terrain.clear();
+		foreground.clear();
+		
+		let floor_tiles : Vec<Vector2i> = self.floor_tiles.iter_shared().collect();
+		for (x, y, cell) in layout.cells() {
+			let coords = self.origin + Vector2i::new(x, y);
+			match cell {
+				Cell::Empty => (),
+				Cell::Floor => {
+					let tile = rng.pick(&floor_tiles).copied().unwrap_or_default();
+					terrain.set_cell_ex(coords).source_id(self.floor_source).atlas_coords(tile).done();
+				},
+				Cell::Wall => {
+					foreground.set_cell_ex(coords).source_id(self.wall_source).atlas_coords(self.wall_tile).done();
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	const SEEDS: [u64; 5] = [0, 1, 42, 1234, 987_654_321];
+	
+	fn generators() -> [fn(i32, i32, &mut Rng) -> LevelLayout; 2] {
+		[rooms_and_corridors, caves]
+	}
+	
+	fn cells(layout: &LevelLayout) -> Vec<Cell> {
+		layout.cells().map(|(_, _, cell)| cell).collect()
+	}
+	
+	fn reachable(layout: &LevelLayout, from: (i32, i32), blocked: &[(i32, i32)]) -> usize {
+		layout.distances(from, blocked).iter().filter(|distance| distance.is_some()).count()
+	}
+	
+	#[test]
+	fn same_seed_same_layout() {
+		for generate in generators() {
+			for seed in SEEDS {
+				let first = generate(30, 30, &mut Rng::new(seed));
+				let second = generate(30, 30, &mut Rng::new(seed));
+				assert_eq!(cells(&first), cells(&second), "seed {}", seed);
+			}
+		}
+	}
+	
+	#[test]
+	fn every_floor_is_reachable() {
+		for generate in generators() {
+			for seed in SEEDS {
+				let layout = generate(30, 30, &mut Rng::new(seed));
+				let floors = layout.floors();
+				assert!(!floors.is_empty(), "seed {}", seed);
+				assert_eq!(reachable(&layout, floors[0], &[]), floors.len(), "seed {}", seed);
+			}
+		}
+	}
+	
+	#[test]
+	fn placement() {
+		for generate in generators() {
+			for seed in SEEDS {
+				let mut rng = Rng::new(seed);
+				let layout = generate(30, 30, &mut rng);
+				let placement = place(&layout, &mut rng, 3, 6).unwrap();
+				let distances = layout.distances(placement.start, &[]);
+				
+				for &(x, y) in placement.wolves.iter().chain(placement.scenery.iter()) {
+					assert!(layout.distance(&distances, x, y).unwrap() >= SAFE_DISTANCE, "seed {}", seed);
+				}
+				
+				// Blocking the scenery's tiles must leave every other floor reachable.
+				let floors = layout.floors().len();
+				assert_eq!(reachable(&layout, placement.start, &placement.scenery) + placement.scenery.len(), floors, "seed {}", seed);
+				assert!(!placement.scenery.contains(&placement.exit));
+			}
+		}
+	}
+}
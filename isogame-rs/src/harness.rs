@@ -0,0 +1,408 @@
+use std::collections::VecDeque;
+
+use godot::prelude::*;
+use godot::classes::Node;
+use godot::classes::INode;
+use godot::classes::Node2D;
+use godot::classes::TileMapLayer;
+use godot::classes::DirAccess;
+use godot::classes::FileAccess;
+
+use crate::creature::Creature;
+use crate::error;
+use crate::error::GameError;
+use crate::error::GameResult;
+use crate::error::LineError;
+use crate::level;
+use crate::level::Level;
+use crate::npc::Npc;
+use crate::player::Player;
+use crate::replay;
+use crate::replay::InputFrame;
+use crate::rng::GameRng;
+use crate::rng::RandomStreams;
+use crate::util::IsometricFacing;
+
+/// Runs scripted tests against real levels, then quits. Failures are printed as errors, and the exit code is 1 if any test failed.
+///
+/// This is meant to be run headless from the command line, or through `cargo test`:
+///
+/// ```text
+/// godot --headless --fixed-fps 60 --path isogame-godot res://run_tests.tscn
+/// ```
+#[derive(GodotClass)]
+#[class(base=Node,init)]
+pub struct TestRunner {
+	/// Directory containing the `.test` scripts to run.
+	#[export]
+	#[init(val = GString::from("res://tests"))]
+	directory: GString,
+	
+	/// Scene used for the player in every test.
+	#[export]
+	#[init(val = GString::from("res://player.tscn"))]
+	player: GString,
+	
+	tests: VecDeque<(GString, Result<TestScript, LineError>)>,
+	current: Option<RunningTest>,
+	run: usize,
+	failures: Vec<String>,
+	base: Base<Node>
+}
+
+#[godot_api]
+impl INode for TestRunner {
+	fn ready(&mut self) {
+		self.tests = find_tests(&self.directory).into_iter().map(|path| {
+			let source = FileAccess::get_file_as_string(&path);
+			let script = TestScript::parse(&source.to_string());
+			(path, script)
+		}).collect();
+	}
+	
+	fn physics_process(&mut self, _delta: f64) {
+		match self.current.take() {
+			Some(test) => self.step(test),
+			None => self.start_next()
+		}
+	}
+}
+
+/// One line of a test script.
+enum Step {
+	Frame(InputFrame),
+	Kill(usize, String),			// Line number and the node path to kill
+	Expect(usize, Expectation)		// Line number and what to check
+}
+
+enum Expectation {
+	At(String, Vector2i),			// Node path and the tile it should be on
+	NotAt(String, Vector2i),		// Node path and a tile it should have left
+	Facing(String, IsometricFacing),
+	Health(String, Comparison, f32),
+	Gone(String),
+	Reserves(String, Vector2i),		// Node path and the tile it should have reserved
+	Solid(Vector2i, bool),			// Tile and whether it should be blocked on the navigation grid
+	Pickup(Vector2i)				// Tile that something should have been dropped on
+}
+
+#[derive(Clone, Copy)]
+enum Comparison {
+	Equal,
+	Less,
+	Greater
+}
+
+/// A test, parsed from a plain text file. Input uses the same format as recordings, one run of identical frames per line,
+/// and expectations are checked once every frame before them has been played:
+///
+/// ```text
+/// level res://tests/arena.tscn
+/// spawn Start
+/// seed 1
+/// wait 10
+/// 1 se
+/// wait 60
+/// expect Player at 1 2
+/// expect Wolf not at 4 4
+/// expect Player facing se
+/// expect Player health < 100
+/// expect Wolf gone
+/// expect Player reserves 1 2
+/// expect solid 1 2
+/// expect open 1 1
+/// expect pickup 1 3
+/// kill Wolf
+/// ```
+struct TestScript {
+	level: GString,
+	spawn: String,
+	seed: u64,
+	steps: Vec<Step>
+}
+
+impl TestScript {
+	fn parse(source: &str) -> Result<Self, LineError> {
+		let mut script = TestScript { level: GString::new(), spawn: "Start".to_string(), seed: 0, steps: Vec::new() };
+		
+		for source_line in error::source_lines(source) {
+			let line = source_line.text;
+			let error = |message: &str| source_line.error(message);
+			
+			let words : Vec<&str> = line.split_whitespace().collect();
+			match words.as_slice() {
+				["level", path] => script.level = GString::from(*path),
+				["spawn", name] => script.spawn = name.to_string(),
+				["seed", seed] => script.seed = seed.parse().map_err(|_| error("invalid seed"))?,
+				["wait", count] => {
+					let count = count.parse().map_err(|_| error("invalid frame count"))?;
+					script.steps.extend((0..count).map(|_| Step::Frame(InputFrame::default())));
+				},
+				["kill", node] => script.steps.push(Step::Kill(source_line.number, node.to_string())),
+				["expect", rest @ ..] => {
+					let expectation = parse_expectation(rest).ok_or_else(|| error("invalid expectation"))?;
+					script.steps.push(Step::Expect(source_line.number, expectation));
+				},
+				_ => {
+					let (count, frame) = replay::parse_frames(line).map_err(|message| error(&message))?;
+					script.steps.extend((0..count).map(|_| Step::Frame(frame.clone())));
+				}
+			}
+		}
+		
+		if script.level.is_empty() { return Err(LineError::new(1, "missing level")); }
+		Ok(script)
+	}
+}
+
+fn parse_expectation(words: &[&str]) -> Option<Expectation> {
+	let tile = |x: &str, y: &str| Some(Vector2i::new(x.parse().ok()?, y.parse().ok()?));
+	
+	let expectation = match words {
+		["solid", x, y] => Expectation::Solid(tile(x, y)?, true),
+		["open", x, y] => Expectation::Solid(tile(x, y)?, false),
+		["pickup", x, y] => Expectation::Pickup(tile(x, y)?),
+		[node, "at", x, y] => Expectation::At(node.to_string(), tile(x, y)?),
+		[node, "not", "at", x, y] => Expectation::NotAt(node.to_string(), tile(x, y)?),
+		[node, "gone"] => Expectation::Gone(node.to_string()),
+		[node, "reserves", x, y] => Expectation::Reserves(node.to_string(), tile(x, y)?),
+		[node, "facing", facing] => {
+			let facing = match *facing {
+				"nw" => IsometricFacing::NW,
+				"ne" => IsometricFacing::NE,
+				"sw" => IsometricFacing::SW,
+				"se" => IsometricFacing::SE,
+				_ => return None
+			};
+			Expectation::Facing(node.to_string(), facing)
+		},
+		[node, "health", comparison, value] => {
+			let comparison = match *comparison {
+				"=" => Comparison::Equal,
+				"<" => Comparison::Less,
+				">" => Comparison::Greater,
+				_ => return None
+			};
+			Expectation::Health(node.to_string(), comparison, value.parse().ok()?)
+		},
+		_ => return None
+	};
+	Some(expectation)
+}
+
+/// A test whose level is loaded, and how far through its steps we've got.
+struct RunningTest {
+	path: GString,
+	script: TestScript,
+	level: Gd<Level>,
+	step: usize
+}
+
+impl TestRunner {
+	/// Load the next test's level, or quit once every test has run.
+	fn start_next(&mut self) {
+		let (path, script) = match self.tests.pop_front() {
+			Some(x) => x,
+			None => return self.finish()
+		};
+		self.run += 1;
+		
+		let script = match script {
+			Ok(x) => x,
+			Err(e) => return self.failures.push(format!("{}: {}", path, e))
+		};
+		
+		match self.load_level(&script) {
+			Ok(level) => self.current = Some(RunningTest { path, script, level, step: 0 }),
+			Err(e) => self.failures.push(format!("{}: couldn't load {}: {}", path, script.level, e))
+		}
+	}
+	
+	/// Check expectations until we reach the next frame of input, then play it.
+	fn step(&mut self, mut test: RunningTest) {
+		while let Some(step) = test.script.steps.get(test.step) {
+			test.step += 1;
+			
+			match step {
+				Step::Frame(frame) => {
+					// The player reads its input on its own physics frame, which runs straight after ours.
+					if let Some(mut player) = test.level.try_get_node_as::<Player>("Player") {
+						player.bind_mut().input = Some(frame.clone());
+					}
+					self.current = Some(test);
+					return;
+				},
+				Step::Kill(line, path) => {
+					if let Err(message) = kill(&test.level, path) {
+						self.failures.push(format!("{}: line {}: {}", test.path, line, message));
+						break;
+					}
+				},
+				Step::Expect(line, expectation) => {
+					if let Err(message) = check(&test.level, expectation) {
+						self.failures.push(format!("{}: line {}: {}", test.path, line, message));
+						break;
+					}
+				}
+			}
+		}
+		
+		// Take the level out of the tree straight away, so the next one doesn't find its entities.
+		let mut level = test.level;
+		self.base_mut().remove_child(&level);
+		level.queue_free();
+	}
+	
+	/// Create the test's level with the player at its spawn point, seeded so that every run is the same.
+	fn load_level(&mut self, script: &TestScript) -> GameResult<Gd<Level>> {
+		let packed_level = try_load(&script.level).map_err(|_| GameError::LoadFailed(script.level.to_string()))?;
+		let packed_player = try_load(&self.player).map_err(|_| GameError::LoadFailed(self.player.to_string()))?;
+		
+		let mut level : Gd<Level> = error::instantiate(&packed_level, &script.level)?;
+		let mut rng = GameRng::new_gd();
+		rng.bind_mut().streams = RandomStreams::new(script.seed);
+		level.bind_mut().set_rng(rng);
+		
+		let generated = level.bind_mut().generate();
+		let spawn_point = generated.and_then(|_| {
+			level.bind().find_spawn_point(&script.spawn)
+				.ok_or_else(|| GameError::MissingSpawnPoint(script.level.to_string(), script.spawn.clone()))
+		});
+		let (coords, facing) = match spawn_point {
+			Ok(x) => x,
+			Err(e) => {
+				level.free();
+				return Err(e);
+			}
+		};
+		
+		let mut player : Gd<Player> = match error::instantiate(&packed_player, &self.player) {
+			Ok(x) => x,
+			Err(e) => {
+				level.free();
+				return Err(e);
+			}
+		};
+		player.set_position(coords);
+		player.bind_mut().character.facing = facing;
+		level.add_child(&player);
+		
+		self.base_mut().add_child(&level);
+		Ok(level)
+	}
+	
+	fn finish(&mut self) {
+		for failure in self.failures.iter() {
+			godot_error!("{}", failure);
+		}
+		godot_print!("Ran {} tests, {} failed.", self.run, self.failures.len());
+		
+		let exit_code = if self.failures.is_empty() { 0 } else { 1 };
+		let mut tree = self.base().get_tree().unwrap();
+		tree.quit_ex().exit_code(exit_code).done();
+		self.base_mut().set_physics_process(false);
+	}
+}
+
+/// Check a single expectation against the current state of the level.
+fn check(level: &Gd<Level>, expectation: &Expectation) -> Result<(), String> {
+	let find = |path: &str| level.get_node_or_null(path).ok_or_else(|| format!("{} doesn't exist", path));
+	
+	match expectation {
+		Expectation::At(path, tile) => {
+			let actual = tile_of(level, path)?;
+			if actual != *tile { return Err(format!("{} is at {}, not {}", path, actual, tile)); }
+		},
+		Expectation::NotAt(path, tile) => {
+			let actual = tile_of(level, path)?;
+			if actual == *tile { return Err(format!("{} is still at {}", path, tile)); }
+		},
+		Expectation::Facing(path, facing) => {
+			let actual = facing_of(&find(path)?).ok_or_else(|| format!("{} can't face anywhere", path))?;
+			if actual != *facing { return Err(format!("{} is facing {}, not {}", path, actual, facing)); }
+		},
+		Expectation::Health(path, comparison, value) => {
+			let actual = health_of(&find(path)?).ok_or_else(|| format!("{} has no health", path))?;
+			let passed = match comparison {
+				Comparison::Equal => actual == *value,
+				Comparison::Less => actual < *value,
+				Comparison::Greater => actual > *value
+			};
+			if !passed { return Err(format!("{} has {} health", path, actual)); }
+		},
+		Expectation::Gone(path) => {
+			if level.has_node(path.as_str()) { return Err(format!("{} still exists", path)); }
+		},
+		Expectation::Reserves(path, tile) => {
+			let node = find(path)?;
+			match level.bind().occupant(*tile) {
+				Some(occupant) if occupant == node => (),
+				Some(occupant) => return Err(format!("tile {} is reserved by {}, not {}", tile, occupant.get_name(), path)),
+				None => return Err(format!("tile {} isn't reserved by anybody", tile))
+			}
+		},
+		Expectation::Solid(tile, solid) => {
+			let actual = level.bind().is_solid(*tile).ok_or("the level has no navigation grid")?;
+			if actual != *solid {
+				let state = |solid: bool| if solid { "solid" } else { "open" };
+				return Err(format!("tile {} is {}, not {}", tile, state(actual), state(*solid)));
+			}
+		},
+		Expectation::Pickup(tile) => {
+			let terrain : Gd<TileMapLayer> = level.try_get_node_as("TerrainLayer").ok_or("the level has no TerrainLayer")?;
+			let mut tree = level.get_tree().ok_or("the level isn't in the scene tree")?;
+			let found = tree.get_nodes_in_group("pickups").iter_shared()
+				.filter_map(|node| node.try_cast::<Node2D>().ok())
+				.any(|node| level::global_to_grid(&terrain, node.get_global_position()) == *tile);
+			if !found { return Err(format!("nothing was dropped on tile {}", tile)); }
+		}
+	}
+	Ok(())
+}
+
+/// Drop a player or creature's health to nothing, so it dies on its next physics frame.
+fn kill(level: &Gd<Level>, path: &str) -> Result<(), String> {
+	let node = level.get_node_or_null(path).ok_or_else(|| format!("{} doesn't exist", path))?;
+	if let Ok(mut player) = node.clone().try_cast::<Player>() {
+		player.bind_mut().data.health = 0.0;
+	} else if let Ok(mut creature) = node.try_cast::<Creature>() {
+		creature.bind_mut().health = 0.0;
+	} else {
+		return Err(format!("{} can't be killed", path));
+	}
+	Ok(())
+}
+
+/// Which tile the node at `path` is on.
+fn tile_of(level: &Gd<Level>, path: &str) -> Result<Vector2i, String> {
+	let node = level.get_node_or_null(path).ok_or_else(|| format!("{} doesn't exist", path))?;
+	let node = node.try_cast::<Node2D>().map_err(|_| format!("{} has no position", path))?;
+	let terrain : Gd<TileMapLayer> = level.try_get_node_as("TerrainLayer").ok_or("the level has no TerrainLayer")?;
+	Ok(level::global_to_grid(&terrain, node.get_global_position()))
+}
+
+fn facing_of(node: &Gd<Node>) -> Option<IsometricFacing> {
+	if let Ok(player) = node.clone().try_cast::<Player>() { return Some(player.bind().character.facing.clone()); }
+	if let Ok(creature) = node.clone().try_cast::<Creature>() { return Some(creature.bind().character.facing.clone()); }
+	if let Ok(npc) = node.clone().try_cast::<Npc>() { return Some(npc.bind().character.facing.clone()); }
+	None
+}
+
+fn health_of(node: &Gd<Node>) -> Option<f32> {
+	if let Ok(player) = node.clone().try_cast::<Player>() { return Some(player.bind().data.health); }
+	if let Ok(creature) = node.clone().try_cast::<Creature>() { return Some(creature.bind().health); }
+	None
+}
+
+/// Find the paths of every test script in a directory, in alphabetical order.
+fn find_tests(directory: &GString) -> Vec<GString> {
+	let directory = directory.to_string();
+	let directory = directory.trim_end_matches('/');
+	
+	let mut tests : Vec<GString> = DirAccess::get_files_at(directory).as_slice().iter()
+		.filter(|file| file.to_string().ends_with(".test"))
+		.map(|file| GString::from(format!("{}/{}", directory, file).as_str()))
+		.collect();
+	tests.sort_by_key(|path| path.to_string());
+	tests
+}
@@ -0,0 +1,131 @@
+use godot::prelude::*;
+use godot::classes::StaticBody2D;
+use godot::classes::IStaticBody2D;
+use godot::classes::Sprite2D;
+
+use crate::inventory::Inventory;
+
+/// The different kinds of object that the player can interact with.
+#[derive(GodotConvert, Var, Export, Clone, Default, Debug, PartialEq)]
+#[godot(via = GString)]
+pub enum InteractableKind {
+	#[default]
+	Sign,
+	Lever,
+	Chest,
+	Door
+}
+
+/// Represents the outcome of the player interacting with an object.
+pub enum InteractionResult {
+	Nothing,
+	Message(GString),
+	Toggled(bool),				// New state of the object
+	Opened(bool),				// New state of a door
+	ReceivedItem(GString, u32),	// Item name and quantity
+	Dialogue(GString, GString),	// Path to a dialogue file and name of the speaker
+}
+
+/// A static object on the grid that the player can interact with by facing it and pressing the interact key.
+/// Its behaviour is determined entirely by its exported properties, so new objects can be built in the editor.
+#[derive(GodotClass)]
+#[class(base=StaticBody2D,init)]
+pub struct Interactable {
+	#[export]
+	kind: InteractableKind,
+	
+	/// Text displayed when interacting. Signs always display it, other objects only if it is set.
+	#[export]
+	message: GString,
+	
+	/// Item given to the player by a chest.
+	#[export]
+	item: GString,
+	
+	#[export]
+	#[init(val = 1)]
+	quantity: u32,
+	
+	/// Item the player must be carrying to open a door. Leave empty for an unlocked door.
+	#[export]
+	key: GString,
+	
+	/// Whether a lever is switched on, a chest has been opened, or a door is open.
+	#[export]
+	pub active: bool,
+	
+	base: Base<StaticBody2D>
+}
+
+#[godot_api]
+impl Interactable {
+	/// Emitted after every interaction, so that other nodes can react to levers and such.
+	#[signal]
+	pub fn interacted(active: bool);
+}
+
+#[godot_api]
+impl IStaticBody2D for Interactable {
+	fn ready(&mut self) {
+		// Interactables are found by the level through this group.
+		self.base_mut().add_to_group("interactables");
+		self.update_sprite();
+	}
+}
+
+impl Interactable {
+	pub fn interact(&mut self, inventory: &Inventory) -> InteractionResult {
+		let result = match self.kind {
+			InteractableKind::Sign => self.message_or(InteractionResult::Nothing),
+			InteractableKind::Lever => {
+				self.active = !self.active;
+				self.message_or(InteractionResult::Toggled(self.active))
+			},
+			InteractableKind::Chest => {
+				if self.active || self.item.is_empty() {
+					InteractionResult::Message("It's empty.".into())
+				} else {
+					self.active = true;
+					InteractionResult::ReceivedItem(self.item.clone(), self.quantity)
+				}
+			},
+			InteractableKind::Door => {
+				if !self.active && !self.key.is_empty() && !inventory.has(&self.key.to_string()) {
+					return InteractionResult::Message("It's locked.".into());
+				}
+				self.active = !self.active;
+				InteractionResult::Opened(self.active)
+			}
+		};
+		
+		self.update_sprite();
+		
+		let active = self.active;
+		let mut sig = self.signals().interacted();
+		sig.emit(active);
+		
+		result
+	}
+	
+	/// Whether characters can walk through this object. Only open doors can be walked through.
+	pub fn is_passable(&self) -> bool {
+		self.kind == InteractableKind::Door && self.active
+	}
+	
+	/// Prefer displaying our message, if one was set, over the given result.
+	fn message_or(&self, result: InteractionResult) -> InteractionResult {
+		if self.message.is_empty() { result } else { InteractionResult::Message(self.message.clone()) }
+	}
+	
+	/// If our sprite has two frames, show the first when inactive and the second when active.
+	fn update_sprite(&mut self) {
+		let mut sprite = match self.base().try_get_node_as::<Sprite2D>("Sprite2D") {
+			Some(x) => x,
+			None => return
+		};
+		
+		if sprite.get_hframes() > 1 {
+			sprite.set_frame(self.active as i32);
+		}
+	}
+}
@@ -0,0 +1,32 @@
+use std::collections::HashMap;
+
+/// Tracks the items carried by the player, by name and quantity.
+#[derive(Clone, Default, Debug)]
+pub struct Inventory {
+	items: HashMap<String, u32>
+}
+
+impl Inventory {
+	pub fn add(&mut self, item: &str, quantity: u32) {
+		*self.items.entry(item.to_string()).or_insert(0) += quantity;
+	}
+	
+	/// Remove some quantity of an item. Returns false (and removes nothing) if we don't have enough.
+	pub fn remove(&mut self, item: &str, quantity: u32) -> bool {
+		let count = self.count(item);
+		if count < quantity { return false; }
+		
+		if count == quantity {
+			self.items.remove(item);
+		} else {
+			self.items.insert(item.to_string(), count - quantity);
+		}
+		true
+	}
+	
+	pub fn count(&self, item: &str) -> u32 { *self.items.get(item).unwrap_or(&0) }
+	
+	pub fn has(&self, item: &str) -> bool { self.count(item) > 0 }
+	
+	pub fn items(&self) -> impl Iterator<Item = (&String, &u32)> { self.items.iter() }
+}
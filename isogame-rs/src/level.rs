@@ -1,39 +1,121 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+
 use godot::prelude::*;
 use godot::classes::Node2D;
 use godot::classes::INode2D;
+use godot::classes::Marker2D;
 use godot::classes::TileMapLayer;
+use godot::classes::FileAccess;
+use godot::classes::PackedScene;
 use godot::classes::AStarGrid2D;
 use godot::classes::a_star_grid_2d::CellShape;
 use godot::classes::a_star_grid_2d::DiagonalMode;
 use godot::classes::a_star_grid_2d::Heuristic;
 
+use crate::creature::Creature;
+use crate::creature::CreatureBook;
+use crate::error;
+use crate::error::GameError;
+use crate::error::GameResult;
+use crate::error::LogError;
+use crate::generate::LevelGenerator;
+use crate::interact::Interactable;
+use crate::interact::InteractionResult;
+use crate::loot::LootBook;
+use crate::npc::Npc;
+use crate::occlusion;
+use crate::occlusion::WallLayer;
+use crate::pickup::Pickup;
 use crate::player::Player;
-use crate::wolf::Wolf;
+use crate::reservation::ReservationGrid;
+use crate::rng;
+use crate::rng::GameRng;
+use crate::scenery::Scenery;
+use crate::spatial;
+use crate::spatial::EntityIndex;
+use crate::faction::Faction;
+use crate::util::IsometricFacing;
+
+/// How many steps away from where it was rolled loot can be dropped, when there isn't room any closer.
+const MAX_DROP_DISTANCE: i32 = 3;
 
 #[derive(GodotClass)]
 #[class(base=Node2D)]
 pub struct Level {
+	/// How many rows of tiles in front of a character are faded out, so that tall walls don't hide it. Anything in the
+	/// `occluders` group fades out too.
+	#[export]
+	occlusion_depth: i32,
+	/// Path to the file defining every kind of creature that can appear in the level.
+	#[export]
+	creatures: GString,
+	/// Path to the file defining every loot table that creatures in the level can drop from.
+	#[export]
+	loot: GString,
+	/// Scene used to drop loot, which must have a `Pickup` as its root.
+	#[export]
+	pickup: GString,
+	
+	creature_book: CreatureBook,
+	loot_book: LootBook,
 	pathfinding_update: f64,
+	generated: bool,
 	tilemap: Option<Gd<TileMapLayer>>,
+	foreground: Option<Gd<TileMapLayer>>,
 	nav: Option<Gd<AStarGrid2D>>,
+	reservations: Gd<ReservationGrid>,
+	index: Gd<EntityIndex>,
+	rng: Gd<GameRng>,
 	base: Base<Node2D>
 }
 
+#[godot_api]
+impl Level {
+	/// Emitted when some text should be displayed to the player.
+	#[signal]
+	pub fn message(text: GString);
+	/// Emitted when a conversation should be started, with the path to its dialogue file and the name of the speaker.
+	#[signal]
+	pub fn dialogue(path: GString, speaker: GString);
+	/// Emitted when the player kills a creature, with the name of its kind and the experience it's worth.
+	#[signal]
+	pub fn creature_died(kind: GString, experience: u32);
+}
+
 #[godot_api]
 impl INode2D for Level {
 	fn init(base: Base<Node2D>) -> Self {
 		Self {
+			occlusion_depth: 3,
+			creatures: GString::from("res://creatures.cre"),
+			loot: GString::from("res://loot.tbl"),
+			pickup: GString::from("res://pickup.tscn"),
+			creature_book: CreatureBook::default(),
+			loot_book: LootBook::default(),
 			pathfinding_update: 0.0,
+			generated: false,
 			tilemap: None,
+			foreground: None,
 			nav: None,
+			reservations: ReservationGrid::new_gd(),
+			index: EntityIndex::new_gd(),
+			rng: GameRng::new_gd(),
 			base
 		}
 	}
 	
 	fn ready(&mut self) {
-		self.initialise_tilemap();
-		self.initialise_pathfinding();
+		// A broken level stays inert, rather than crashing the game.
+		let context = format!("Level {} is broken", self.base().get_scene_file_path());
+		let initialised = self.generate()
+			.and_then(|_| self.initialise_tilemap())
+			.and_then(|_| self.initialise_pathfinding());
+		if initialised.log_error(&context).is_none() { return; }
 		self.update_pathfinding();
+		self.load_creatures();
+		self.load_loot();
 		
 		// Initialise all entities within the tilemap.
 		let mut tree = self.base().get_tree().unwrap();
@@ -42,10 +124,32 @@ impl INode2D for Level {
 		for node in entities.iter_shared() {
 			self.lock_to_grid(&node);
 			self.register_signals(&node);
+			self.add_to_index(&node);
+		}
+		
+		// Scenery objects reserve their own tiles, like entities, since they can be pushed or destroyed.
+		let scenery = tree.get_nodes_in_group("scenery");
+		for node in scenery.iter_shared() {
+			self.lock_to_grid(&node);
+			self.register_signals(&node);
+			self.add_to_index(&node);
+		}
+		
+		// Interactables need to be on the grid too, so they can be found from the tile in front of the player.
+		let interactables = tree.get_nodes_in_group("interactables");
+		for node in interactables.iter_shared() {
+			self.lock_to_grid(&node);
 		}
 	}
 	
-	fn process(&mut self, delta: f64) {
+	fn process(&mut self, _delta: f64) {
+		if self.nav.is_none() { return; }
+		self.update_occlusion();
+	}
+	
+	fn physics_process(&mut self, delta: f64) {
+		if self.nav.is_none() { return; }
+		
 		self.pathfinding_update += delta;
 		if self.pathfinding_update >= 1.0 {
 			self.update_pathfinding();
@@ -55,12 +159,99 @@ impl INode2D for Level {
 }
 
 impl Level {
-	fn initialise_tilemap(&mut self) {
-		let tilemap : Gd<TileMapLayer> = self.base().get_node_as("TerrainLayer");
+	/// Find a spawn point by name, returning its position locked to the grid and the direction to face.
+	/// Spawn points must be direct children of the level. This works before the level enters the scene tree.
+	pub fn find_spawn_point(&self, name: &str) -> Option<(Vector2, IsometricFacing)> {
+		let spawn_point = self.base().try_get_node_as::<SpawnPoint>(name)?;
+		let tilemap : Gd<TileMapLayer> = self.base().try_get_node_as("TerrainLayer")?;
+		
+		let tile = level_to_grid(&tilemap, spawn_point.get_position());
+		let position = grid_to_level(&tilemap, tile);
+		
+		let facing = spawn_point.bind().facing.clone();
+		Some((position, facing))
+	}
+	
+	/// Fill in the level from its `LevelGenerator` child, if it has one. This only happens once, and works before the level enters the scene tree.
+	pub fn generate(&mut self) -> GameResult<()> {
+		if self.generated { return Ok(()); }
+		self.generated = true;
+		
+		let generator = match self.base().try_get_node_as::<LevelGenerator>("LevelGenerator") {
+			Some(x) => x,
+			None => return Ok(())
+		};
+		let level = self.to_gd().upcast();
+		generator.bind().generate(level, &mut self.rng.bind_mut())
+	}
+	
+	/// Whether a tile is blocked on the navigation grid, or `None` if the level couldn't be set up.
+	pub fn is_solid(&self, tile: Vector2i) -> Option<bool> {
+		Some(self.nav.as_ref()?.is_point_solid(tile))
+	}
+	
+	/// Whoever has reserved a tile, by standing on it or moving onto it.
+	pub fn occupant(&self, tile: Vector2i) -> Option<Gd<Node>> {
+		Gd::try_from_instance_id(self.reservations.bind().tiles.owner(tile)?).ok()
+	}
+	
+	/// The area covered by the level's terrain, in global coordinates, or `None` if there isn't any.
+	pub fn bounds(&self) -> Option<Rect2> {
+		let tilemap = self.tilemap.as_ref()?;
+		let tile_size = tilemap.get_tile_set()?.get_tile_size().cast_float();
+		
+		let mut bounds : Option<Rect2> = None;
+		for tile in tilemap.get_used_cells().iter_shared() {
+			let centre = grid_to_global(tilemap, tile);
+			let rect = Rect2::new(centre - tile_size / 2.0, tile_size);
+			bounds = Some(match bounds {
+				Some(x) => x.merge(rect),
+				None => rect
+			});
+		}
+		bounds
+	}
+	
+	/// Share the game's random number generator with everything in the level.
+	pub fn set_rng(&mut self, rng: Gd<GameRng>) {
+		self.rng = rng;
+	}
+	
+	/// Record the state of every interactable in the level.
+	pub fn save_state(&self) -> LevelState {
+		let mut state = LevelState::default();
+		let mut tree = self.base().get_tree().unwrap();
+		let interactables = tree.get_nodes_in_group("interactables");
+		
+		for node in interactables.iter_shared() {
+			// The level we're leaving can still be in the tree, waiting to be freed.
+			if !self.base().is_ancestor_of(&node) { continue; }
+			
+			let path = self.base().get_path_to(&node).to_string();
+			let interactable : Gd<Interactable> = node.cast();
+			state.interactables.insert(path, interactable.bind().active);
+		}
+		state
+	}
+	
+	/// Restore a previously saved state. This must be called before the level enters the scene tree.
+	pub fn restore_state(&mut self, state: &LevelState) {
+		for (path, active) in state.interactables.iter() {
+			if let Some(mut interactable) = self.base().try_get_node_as::<Interactable>(path.as_str()) {
+				interactable.bind_mut().active = *active;
+			}
+		}
+	}
+	
+	fn initialise_tilemap(&mut self) -> GameResult<()> {
+		let tilemap : Gd<TileMapLayer> = error::find_node(&self.base(), "TerrainLayer")?;
+		let foreground : Gd<TileMapLayer> = error::find_node(&self.base(), "ForegroundLayer")?;
 		self.tilemap = Some(tilemap);
+		self.foreground = Some(foreground);
+		Ok(())
 	}
 	
-	fn initialise_pathfinding(&mut self) {
+	fn initialise_pathfinding(&mut self) -> GameResult<()> {
 		let tilemap = self.tilemap.as_ref().unwrap();
 		
 		// Basic configuration.
@@ -70,7 +261,7 @@ impl Level {
 		nav.set_default_compute_heuristic(Heuristic::MANHATTAN);
 
 		// Set the correct tile size.
-		let tileset = tilemap.get_tile_set().unwrap();
+		let tileset = tilemap.get_tile_set().ok_or_else(|| GameError::MissingTileSet("TerrainLayer".to_string()))?;
 		let tile_size = tileset.get_tile_size().cast_float();
 		nav.set_cell_size(tile_size);
 
@@ -80,33 +271,103 @@ impl Level {
 		nav.set_region(region);
 
 		nav.update();
+		self.reservations.bind_mut().set_nav(nav.clone());
 		self.nav = Some(nav);
+		Ok(())
 	}
 	
 	fn update_pathfinding(&mut self) {
-		let foreground : Gd<TileMapLayer> = self.base().get_node_as("ForegroundLayer");
-		
 		let mut tree = self.base().get_tree().unwrap();
 		let tilemap =  self.tilemap.as_ref().unwrap();
-		let nav = self.nav.as_mut().unwrap();
+		let foreground = self.foreground.as_ref().unwrap();
+		
+		// Static solidity is worked out from scratch every time, so anything that has gone since last time, like an
+		// object that was broken, stops blocking its tile.
+		let mut reservations = self.reservations.bind_mut();
+		let previous = reservations.tiles.clear_statics();
 		
 		// Mark all foreground tiles as impassable, since they represent walls and such.
 		let foreground_tiles = foreground.get_used_cells();
 		for tile in foreground_tiles.iter_shared() {
-			nav.set_point_solid(tile);
+			reservations.tiles.set_static(tile, true);
 		}
 		
-		// Mark all static "scenery" as impassable.
+		// Mark all static "scenery" as impassable. Scenery objects that can move reserve their own tiles instead.
 		let entities = tree.get_nodes_in_group("scenery");
 		for entity in entities.iter_shared() {
-			let node : Gd<Node2D> = entity.cast();
+			let node : Gd<Node2D> = match entity.try_cast::<Scenery>() {
+				Ok(_) => continue,
+				Err(entity) => entity.cast()
+			};
 			let pos = node.get_position();
-			let tile = global_to_grid(&tilemap, pos);
-			nav.set_point_solid(tile);
+			let tile = global_to_grid(tilemap, pos);
+			reservations.tiles.set_static(tile, true);
+		}
+		
+		// Mark all interactables as impassable, unless they can be walked through (like an open door).
+		// Open doors are made passable when they are opened, since somebody might be standing in them now.
+		let interactables = tree.get_nodes_in_group("interactables");
+		for node in interactables.iter_shared() {
+			let interactable : Gd<Interactable> = node.cast();
+			if interactable.bind().is_passable() { continue; }
+			let tile = global_to_grid(tilemap, interactable.get_position());
+			reservations.tiles.set_static(tile, true);
 		}
 		
-		// TODO register when things disappear, not just when they appear
-		// This would be better handled as some kind of signal emitted by scenery objects when they appear or disappear.
+		let changed : Vec<Vector2i> = previous.into_iter().chain(reservations.tiles.statics()).collect();
+		for tile in changed {
+			reservations.update_tile(tile);
+		}
+	}
+	
+	/// Record which tile an entity or scenery object starts on, and forget about it once it leaves the tree. It moves
+	/// itself in the index from then on.
+	fn add_to_index(&mut self, node: &Gd<Node>) {
+		let tilemap = self.tilemap.as_ref().unwrap();
+		let node2d : Gd<Node2D> = match node.clone().try_cast() {
+			Ok(x) => x,
+			Err(_) => return
+		};
+		let entity = node.instance_id();
+		self.index.bind_mut().add(entity, global_to_grid(tilemap, node2d.get_position()), Faction::of(node));
+		
+		let mut index = self.index.clone();
+		node.signals().tree_exiting().connect(move || index.bind_mut().remove(entity));
+	}
+	
+	/// Fade out walls and tall scenery standing in front of any character, worked out from where everyone is on the grid.
+	fn update_occlusion(&mut self) {
+		let mut tree = self.base().get_tree().unwrap();
+		let tilemap = self.tilemap.as_ref().unwrap();
+		
+		let mut hidden : HashSet<Vector2i> = HashSet::new();
+		for node in tree.get_nodes_in_group("entities").iter_shared() {
+			let node : Gd<Node2D> = match node.try_cast() {
+				Ok(x) => x,
+				Err(_) => continue
+			};
+			let tile = global_to_grid(tilemap, node.get_global_position());
+			hidden.extend(occlusion::occluding_tiles(tile, self.occlusion_depth));
+		}
+		
+		// Plain tile layers can't fade individual tiles, so they're left alone.
+		if let Some(mut walls) = self.foreground.clone().and_then(|layer| layer.try_cast::<WallLayer>().ok()) {
+			let faded = hidden.iter().copied().filter(|tile| walls.get_cell_source_id(*tile) != -1).collect();
+			walls.bind_mut().set_faded(faded);
+		}
+		
+		for node in tree.get_nodes_in_group("occluders").iter_shared() {
+			let mut node : Gd<Node2D> = match node.try_cast() {
+				Ok(x) => x,
+				Err(_) => continue
+			};
+			let tile = global_to_grid(tilemap, node.get_global_position());
+			let alpha = if hidden.contains(&tile) { occlusion::FADED_ALPHA } else { 1.0 };
+			
+			let mut colour = node.get_modulate();
+			colour.a = alpha;
+			node.set_modulate(colour);
+		}
 	}
 	
 	/// Locks an entity's global position to the isometric grid of the tilemap.
@@ -116,58 +377,316 @@ impl Level {
 		// First, convert the entity's global coordinates to grid coordinates.
 		let mut node2d : Gd<Node2D> = node.clone().cast();
 		let pos = node2d.get_position();
-		let grid_pos = global_to_grid(&tilemap, pos);
+		let grid_pos = global_to_grid(tilemap, pos);
 		
 		// Then convert them back into global coordinates.
-		let new_pos = grid_to_global(&tilemap, grid_pos);
+		let new_pos = grid_to_global(tilemap, grid_pos);
 		node2d.set_position(new_pos);
 	}
 	
 	/// Registers signal handlers.
 	fn register_signals(&mut self, node: &Gd<Node>) {
-		match node.get_class().to_string().as_str() {
-			"Player" => self.register_player_signals(node.clone()),
-			"Wolf" => self.register_wolf_signals(node.clone()),
-			_ => ()
+		let node = match node.clone().try_cast::<Player>() {
+			Ok(player) => return self.register_player_signals(player),
+			Err(node) => node
+		};
+		let node = match node.try_cast::<Creature>() {
+			Ok(creature) => return self.register_creature_signals(creature),
+			Err(node) => node
+		};
+		let node = match node.try_cast::<Npc>() {
+			Ok(npc) => return self.register_npc_signals(npc),
+			Err(node) => node
 		};
+		if let Ok(scenery) = node.try_cast::<Scenery>() {
+			self.register_scenery_signals(scenery);
+		}
 	}
 	
-	fn register_player_signals(&mut self, node: Gd<Node>) {
-		let player : Gd<Player> = node.cast();
-		player.signals().reserve_tile().connect_other(self, Self::on_reserve_tile);
-		player.signals().unreserve_tile().connect_other(self, Self::on_unreserve_tile);
+	fn register_player_signals(&mut self, player: Gd<Player>) {
 		player.signals().update_nav().connect_other(self, Self::on_update_nav_player);
+		player.signals().interact().connect_other(self, Self::on_interact);
+		player.signals().push().connect_other(self, Self::on_push);
 	}
 	
-	fn register_wolf_signals(&mut self, node: Gd<Node>) {
-		let wolf : Gd<Wolf> = node.cast();
-		wolf.signals().reserve_tile().connect_other(self, Self::on_reserve_tile);
-		wolf.signals().unreserve_tile().connect_other(self, Self::on_unreserve_tile);
-		wolf.signals().update_nav().connect_other(self, Self::on_update_nav_wolf);
+	/// Creatures are given their definition here, since it comes from the level's creature file.
+	fn register_creature_signals(&mut self, mut creature: Gd<Creature>) {
+		let kind = creature.bind().kind.to_string();
+		let definition = self.creature_book.get(&kind).cloned().ok_or(GameError::UnknownCreature(kind));
+		let result = definition.and_then(|definition| creature.bind_mut().set_definition(definition));
+		let context = format!("Creature {} is broken", creature.get_name());
+		if result.log_error(&context).is_none() { return; }
+		
+		creature.signals().update_nav().connect_other(self, Self::on_update_nav_creature);
+		creature.signals().dead().connect_other(self, |this: &mut Self, kind: GString, tile, killer| this.on_creature_death(&kind.to_string(), tile, killer));
 	}
 	
-	fn on_reserve_tile(&mut self, tile: Vector2i) {
+	fn register_npc_signals(&mut self, npc: Gd<Npc>) {
+		npc.signals().update_nav().connect_other(self, Self::on_update_nav_npc);
+	}
+	
+	fn register_scenery_signals(&mut self, scenery: Gd<Scenery>) {
+		scenery.signals().update_nav().connect_other(self, Self::on_update_nav_scenery);
+		scenery.signals().destroyed().connect_other(self, Self::on_scenery_destroyed);
+	}
+	
+	fn on_update_nav_player(&mut self, mut instance: Gd<Player>) {
+		let tilemap = self.tilemap.as_ref().unwrap();
+		instance.bind_mut().character.set_tilemap(tilemap.clone());
 		let nav = self.nav.as_mut().unwrap();
-		nav.set_point_solid(tile);
+		let (owner, position) = (instance.instance_id(), instance.get_position());
+		instance.bind_mut().character.set_nav(nav.clone(), self.reservations.clone(), self.index.clone(), owner, position);
 	}
 	
-	fn on_unreserve_tile(&mut self, tile: Vector2i) {
+	fn on_interact(&mut self, mut player: Gd<Player>, tile: Vector2i) {
+		let result = if let Some(interactable) = self.get_interactable(tile) {
+			self.use_interactable(interactable, &player, tile)
+		} else if let Some(mut npc) = self.find_at::<Npc>(tile) {
+			let tilemap = self.tilemap.as_ref().unwrap();
+			let player_tile = global_to_grid(tilemap, player.get_position());
+			let path = npc.bind_mut().talk(player_tile);
+			InteractionResult::Dialogue(path, npc.get_name().into())
+		} else if let Some(mut creature) = self.find_at::<Creature>(tile) {
+			// Creatures can't be interacted with, only attacked.
+			let damage = player.bind().data.damage;
+			creature.bind_mut().damage(damage, &player.clone().upcast());
+			InteractionResult::Nothing
+		} else if let Some(mut scenery) = self.find_at::<Scenery>(tile) {
+			// If there's nothing to interact with, try to break whatever is there instead.
+			if scenery.bind().is_destructible() {
+				let damage = player.bind().data.damage;
+				scenery.bind_mut().damage(damage);
+			}
+			InteractionResult::Nothing
+		} else {
+			InteractionResult::Nothing
+		};
+		
+		match result {
+			InteractionResult::Nothing => (),
+			InteractionResult::Message(text) => self.show_message(text),
+			InteractionResult::Toggled(active) => {
+				let text = if active { "Switched on." } else { "Switched off." };
+				self.show_message(text.into());
+			},
+			InteractionResult::Opened(open) => {
+				let text = if open { "The door opens." } else { "The door closes." };
+				self.show_message(text.into());
+			},
+			InteractionResult::ReceivedItem(item, quantity) => {
+				player.bind_mut().data.inventory.add(&item.to_string(), quantity);
+				self.show_message(format!("Received {} x{}.", item, quantity).into());
+			},
+			InteractionResult::Dialogue(path, speaker) => {
+				if path.is_empty() { return; }
+				let mut sig = self.signals().dialogue();
+				sig.emit(&path, &speaker);
+			}
+		}
+	}
+	
+	fn use_interactable(&mut self, mut interactable: Gd<Interactable>, player: &Gd<Player>, tile: Vector2i) -> InteractionResult {
+		// Don't close a door on whoever is standing in it.
+		if interactable.bind().is_passable() && self.reservations.bind().tiles.owner(tile).is_some() {
+			return InteractionResult::Message("Something is in the way.".into());
+		}
+		
+		let result = interactable.bind_mut().interact(&player.bind().data.inventory);
+		
+		// Update the navigation grid immediately, in case a door was opened or closed.
+		let passable = interactable.bind().is_passable();
+		let mut reservations = self.reservations.bind_mut();
+		reservations.tiles.set_static(tile, !passable);
+		reservations.update_tile(tile);
+		
+		result
+	}
+	
+	fn on_push(&mut self, tile: Vector2i, facing: GString) {
+		if let Some(mut scenery) = self.find_at::<Scenery>(tile) {
+			scenery.bind_mut().push(IsometricFacing::from_godot(facing));
+		}
+	}
+	
+	/// Creatures drop their loot however they die, but only count as killed if the player killed them.
+	fn on_creature_death(&mut self, kind: &str, tile: Vector2i, killer: Option<Gd<Node>>) {
+		let definition = match self.creature_book.get(kind) {
+			Some(x) => x.clone(),
+			None => return
+		};
+		
+		if let Some(table) = &definition.loot {
+			self.drop_loot(table, tile).log_error("Creature couldn't drop its loot");
+		}
+		
+		let by_player = killer.is_some_and(|killer| killer.try_cast::<Player>().is_ok());
+		if !by_player { return; }
+		let mut sig = self.signals().creature_died();
+		sig.emit(&GString::from(&definition.name), definition.experience);
+	}
+	
+	fn on_scenery_destroyed(&mut self, tile: Vector2i, loot: GString, quantity: u32) {
+		if loot.is_empty() { return; }
+		self.drop_items(vec![(loot.to_string(), quantity)], tile).log_error("Scenery couldn't drop its loot");
+	}
+	
+	/// Roll a loot table, and drop whatever comes up.
+	fn drop_loot(&mut self, id: &str, tile: Vector2i) -> GameResult<()> {
+		let table = self.loot_book.get(id).cloned().ok_or(GameError::UnknownLootTable(id.to_string()))?;
+		let drops = table.roll(self.rng.bind_mut().stream(rng::stream::LOOT));
+		self.drop_items(drops, tile)
+	}
+	
+	/// Drop a pickup for each item, spread out over the free tiles closest to `tile`.
+	fn drop_items(&mut self, drops: Vec<(String, u32)>, tile: Vector2i) -> GameResult<()> {
+		if drops.is_empty() { return Ok(()); }
+		
+		let scene = try_load::<PackedScene>(&self.pickup).map_err(|_| GameError::LoadFailed(self.pickup.to_string()))?;
+		let tilemap = self.tilemap.clone().unwrap();
+		let mut taken = self.pickup_tiles();
+		
+		for (item, quantity) in drops {
+			let drop_tile = self.find_free_tile(tile, &taken);
+			taken.insert(drop_tile);
+			
+			let mut pickup : Gd<Pickup> = error::instantiate(&scene, &self.pickup)?;
+			pickup.set_position(grid_to_level(&tilemap, drop_tile));
+			pickup.bind_mut().item = GString::from(&item);
+			pickup.bind_mut().quantity = quantity;
+			self.base_mut().call_deferred("add_child", &[pickup.to_variant()]);
+		}
+		Ok(())
+	}
+	
+	/// Every tile that already has a pickup lying on it.
+	fn pickup_tiles(&self) -> HashSet<Vector2i> {
+		let mut tree = self.base().get_tree().unwrap();
+		let tilemap = self.tilemap.as_ref().unwrap();
+		tree.get_nodes_in_group("pickups").iter_shared()
+			.filter_map(|node| node.try_cast::<Node2D>().ok())
+			.map(|node| global_to_grid(tilemap, node.get_global_position()))
+			.collect()
+	}
+	
+	/// The closest open tile to `origin` that isn't taken, searching outwards one step at a time over the navigation
+	/// grid. If there isn't one close enough, things are dropped on `origin` anyway.
+	fn find_free_tile(&self, origin: Vector2i, taken: &HashSet<Vector2i>) -> Vector2i {
+		let mut tilemap = self.tilemap.clone().unwrap();
+		let nav = self.nav.as_ref().unwrap();
+		let open = |tile: Vector2i| nav.is_in_boundsv(tile) && !nav.is_point_solid(tile);
+		
+		let mut visited = HashSet::from([origin]);
+		let mut frontier = VecDeque::from([origin]);
+		while let Some(tile) = frontier.pop_front() {
+			if spatial::distance(origin, tile) > MAX_DROP_DISTANCE { break; }
+			if open(tile) && !taken.contains(&tile) { return tile; }
+			
+			for neighbour in tilemap.get_surrounding_cells(tile).iter_shared() {
+				if open(neighbour) && visited.insert(neighbour) { frontier.push_back(neighbour); }
+			}
+		}
+		origin
+	}
+	
+	fn load_creatures(&mut self) {
+		if self.creatures.is_empty() { return; }
+		
+		let source = FileAccess::get_file_as_string(&self.creatures);
+		let book = CreatureBook::parse(&source.to_string()).map_err(|e| GameError::Creature(self.creatures.to_string(), e));
+		if let Some(book) = book.log_error("Failed to load creatures") {
+			self.creature_book = book;
+		}
+	}
+	
+	fn load_loot(&mut self) {
+		if self.loot.is_empty() { return; }
+		
+		let source = FileAccess::get_file_as_string(&self.loot);
+		let book = LootBook::parse(&source.to_string()).map_err(|e| GameError::Loot(self.loot.to_string(), e));
+		if let Some(book) = book.log_error("Failed to load loot tables") {
+			self.loot_book = book;
+		}
+	}
+	
+	/// Find something of a given class on a tile, if there is one.
+	fn find_at<T>(&self, tile: Vector2i) -> Option<Gd<T>>
+	where
+		T: GodotClass + Inherits<Node>
+	{
+		self.index.bind().at::<T>(tile).next()
+	}
+	
+	/// Find the interactable object on a given tile, if there is one.
+	fn get_interactable(&mut self, tile: Vector2i) -> Option<Gd<Interactable>> {
+		let tilemap = self.tilemap.as_ref().unwrap();
+		let mut tree = self.base().get_tree().unwrap();
+		let interactables = tree.get_nodes_in_group("interactables");
+		
+		for node in interactables.iter_shared() {
+			let interactable : Gd<Interactable> = node.cast();
+			if global_to_grid(tilemap, interactable.get_position()) == tile {
+				return Some(interactable);
+			}
+		}
+		None
+	}
+	
+	fn show_message(&mut self, text: GString) {
+		if text.is_empty() { return; }
+		
+		let mut sig = self.signals().message();
+		sig.emit(&text);
+	}
+	
+	fn on_update_nav_creature(&mut self, mut instance: Gd<Creature>) {
+		let tilemap = self.tilemap.as_ref().unwrap();
+		instance.bind_mut().character.set_tilemap(tilemap.clone());
 		let nav = self.nav.as_mut().unwrap();
-		nav.set_point_solid_ex(tile).solid(false).done();
+		let (owner, position) = (instance.instance_id(), instance.get_position());
+		instance.bind_mut().character.set_nav(nav.clone(), self.reservations.clone(), self.index.clone(), owner, position);
 	}
 	
-	fn on_update_nav_player(&mut self, mut instance: Gd<Player>) {
+	fn on_update_nav_npc(&mut self, mut instance: Gd<Npc>) {
 		let tilemap = self.tilemap.as_ref().unwrap();
 		instance.bind_mut().character.set_tilemap(tilemap.clone());
 		let nav = self.nav.as_mut().unwrap();
-		instance.bind_mut().character.set_nav(nav.clone());
+		let (owner, position) = (instance.instance_id(), instance.get_position());
+		instance.bind_mut().character.set_nav(nav.clone(), self.reservations.clone(), self.index.clone(), owner, position);
+		instance.bind_mut().rng = Some(self.rng.clone());
 	}
 	
-	fn on_update_nav_wolf(&mut self, mut instance: Gd<Wolf>) {
+	fn on_update_nav_scenery(&mut self, mut instance: Gd<Scenery>) {
 		let tilemap = self.tilemap.as_ref().unwrap();
 		instance.bind_mut().character.set_tilemap(tilemap.clone());
 		let nav = self.nav.as_mut().unwrap();
-		instance.bind_mut().character.set_nav(nav.clone());
+		let (owner, position) = (instance.instance_id(), instance.get_position());
+		instance.bind_mut().character.set_nav(nav.clone(), self.reservations.clone(), self.index.clone(), owner, position);
+	}
+}
+
+/// A named place in a level where the player can arrive from a warp.
+#[derive(GodotClass)]
+#[class(base=Marker2D,init)]
+pub struct SpawnPoint {
+	/// The direction the player faces after arriving.
+	#[export]
+	pub facing: IsometricFacing,
+	
+	base: Base<Marker2D>
+}
+
+/// The persistent state of a level, which survives it being unloaded and loaded again.
+#[derive(Clone, Default)]
+pub struct LevelState {
+	interactables: HashMap<String, bool>	// Node path and whether it is active
+}
+
+impl LevelState {
+	pub fn interactables(&self) -> &HashMap<String, bool> { &self.interactables }
+	
+	/// Rebuild a level's state from previously saved data.
+	pub fn restore(interactables: HashMap<String, bool>) -> Self {
+		Self { interactables }
 	}
 }
 
@@ -180,3 +699,15 @@ pub fn global_to_grid(tilemap: &TileMapLayer, coords: Vector2) -> Vector2i {
 	let local_coords = tilemap.to_local(coords);
 	tilemap.local_to_map(local_coords)
 }
+
+/// Like `global_to_grid`, but for positions relative to the level. This works outside the scene tree, where global transforms aren't available.
+pub fn level_to_grid(tilemap: &TileMapLayer, coords: Vector2) -> Vector2i {
+	let local_coords = tilemap.get_transform().affine_inverse() * coords;
+	tilemap.local_to_map(local_coords)
+}
+
+/// Like `grid_to_global`, but for positions relative to the level. This works outside the scene tree, where global transforms aren't available.
+pub fn grid_to_level(tilemap: &TileMapLayer, coords: Vector2i) -> Vector2 {
+	let local_coords = tilemap.map_to_local(coords);
+	tilemap.get_transform() * local_coords
+}
@@ -1,13 +1,51 @@
+use std::collections::HashMap;
+
 use godot::prelude::*;
-use godot::classes::Node;
 use godot::classes::Node2D;
 use godot::classes::INode2D;
 use godot::classes::Area2D;
 use godot::classes::IArea2D;
 use godot::classes::CanvasLayer;
+use godot::classes::Label;
 use godot::classes::PackedScene;
+use godot::classes::FileAccess;
+use godot::classes::DisplayServer;
+use godot::classes::Os;
+use godot::classes::node::ProcessMode;
+use godot::classes::file_access::ModeFlags;
+use godot::global::randi;
 
+use crate::camera::GameCamera;
+use crate::control::DialogueBox;
+use crate::control::MessageBox;
+use crate::control::QuestLogPanel;
+use crate::control::ScreenFade;
+use crate::dialogue::Dialogue;
+use crate::dialogue::DialogueAction;
+use crate::dialogue::DialogueContext;
+use crate::dialogue::DialogueRunner;
+use crate::error;
+use crate::error::GameError;
+use crate::error::GameResult;
+use crate::error::LogError;
+use crate::faction::Faction;
+use crate::inventory::Inventory;
+use crate::level::Level;
+use crate::level::LevelState;
+use crate::loader::LoadStatus;
+use crate::loader::SceneLoader;
 use crate::player::Player;
+use crate::player::PlayerData;
+use crate::progression::Perk;
+use crate::quest::QuestBook;
+use crate::quest::QuestEvent;
+use crate::quest::QuestLog;
+use crate::replay::InputFrame;
+use crate::replay::InputRecording;
+use crate::rng::GameRng;
+use crate::rng::RandomStreams;
+use crate::save::SaveGame;
+use crate::state::GameState;
 use crate::util::IsometricFacing;
 
 #[derive(GodotClass)]
@@ -25,42 +63,183 @@ struct LevelManager {
     #[export]
     player_facing: IsometricFacing,
     
-    current_level: Option<Gd<Node>>,
-    warp: bool,
+    /// Path to the file containing every quest in the game.
+    #[export]
+    quests: GString,
+    
+    /// Where to save the game whenever the player changes level. Leave empty to disable saving.
+    #[export]
+    save_path: GString,
+    
+    /// Seed for all of the game's randomness. Leave at 0 to pick a new one every run. Ignored when resuming from a save file,
+    /// which stores the seed it was started with.
+    #[export]
+    seed: i64,
+    
+    /// Record the player's input to this file when the game closes, so the session can be replayed. Leave empty to disable.
+    /// Can also be set with `--record=<path>` on the command line.
+    #[export]
+    record_path: GString,
+    
+    /// Replay the input recorded in this file instead of reading the keyboard. Leave empty to disable.
+    /// Can also be set with `--replay=<path>` on the command line.
+    #[export]
+    replay_path: GString,
+    
+    current_level: Option<Gd<Level>>,
+    current_level_path: GString,
+    level_states: HashMap<String, LevelState>,
+    state: GameState,
+    quest_book: QuestBook,
+    quest_log: QuestLog,
+    dialogue: Option<DialogueRunner>,
+    #[init(val = GameRng::new_gd())]
+    rng: Gd<GameRng>,
+    transition: Transition,
+    input: InputMode,
+    pending_choice: Option<i64>,
+    /// Whether the dialogue box is showing the perks to pick from, rather than a conversation.
+    choosing_perk: bool,
     base: Base<Node2D>
 }
 
 #[godot_api]
 impl INode2D for LevelManager {
 	fn ready(&mut self) {		
-		let packed_level : Gd<PackedScene> = load(&self.level);
-		let packed_player : Gd<PackedScene> = load(&self.player);
+		self.load_quests();
+		self.read_command_line();
+		self.seed_rng();
 		
-		let level = self.load_level(packed_level, packed_player, self.player_coords, self.player_facing.clone());
-		self.current_level = Some(level);
+		// Recordings always start from a new game, so that they replay the same way regardless of the save file.
+		let saved_player_data = match (self.record_path.is_empty(), self.replay_path.is_empty()) {
+			(true, true) => self.load_game(),
+			_ => None
+		};
+		self.start_input();
+		
+		let context = format!("Failed to start the game in {}", self.level);
+		if self.start_level().log_error(&context).is_none() { return; }
+		
+		// Restore player data from the save file.
+		if let Some(player_data) = saved_player_data && let Some(mut player) = self.current_player() {
+			player.bind_mut().data = player_data;
+		}
+		
+		// Perks earned before the game was saved can still be picked.
+		self.offer_perk();
 		
 		self.register_warp_signals();
+		self.register_dialogue_signals();
+		if let Some(mut camera) = self.camera() { camera.bind_mut().set_rng(self.rng.clone()); }
+		
+		// Fade in from black when the game starts.
+		if let Some(mut fade) = self.ui::<ScreenFade>("FadeLayer/ScreenFade") {
+			fade.bind_mut().cover();
+			fade.bind_mut().fade_in(FADE_DURATION);
+		}
+		self.set_input_locked(true);
+		self.set_level_paused(true);
+		self.transition = Transition::FadingIn;
 	}
 	
-	fn process(&mut self, _delta: f64) {
-		if self.warp {
-			let packed_level : Gd<PackedScene> = load(&self.level);
-			let packed_player : Gd<PackedScene> = load(&self.player);
-			
-			self.change_level(packed_level, packed_player, self.player_coords, self.player_facing.clone());
-			self.register_warp_signals();
-			self.warp = false;
+	fn process(&mut self, delta: f64) {
+		self.process_transition(delta);
+	}
+	
+	fn physics_process(&mut self, _delta: f64) {
+		// Levels are paused during transitions, so input is only counted while one is being played.
+		if !matches!(self.transition, Transition::None) { return; }
+		
+		let frame = match self.next_input_frame() {
+			Some(x) => x,
+			None => return
+		};
+		
+		if frame.quest_log && let Some(mut panel) = self.ui::<QuestLogPanel>("QuestLayer/QuestLogPanel") {
+			panel.bind_mut().toggle();
+			self.refresh_quest_log();
+		}
+		if let Some(index) = frame.choice {
+			self.choose_dialogue(index);
+		}
+		
+		// The player handles the rest of the input on its own physics frame, which runs straight after ours.
+		if let Some(mut player) = self.current_player() {
+			player.bind_mut().input = Some(frame);
+		}
+	}
+	
+	fn exit_tree(&mut self) {
+		if let InputMode::Recording(recording) = &self.input {
+			let mut file = match FileAccess::open(&self.record_path, ModeFlags::WRITE) {
+				Some(x) => x,
+				None => {
+					godot_error!("Failed to save the recording to {}", self.record_path);
+					return;
+				}
+			};
+			file.store_string(&recording.to_text());
 		}
 	}
 }
 
+/// How long it takes to fade to or from black when changing level, in seconds.
+const FADE_DURATION: f32 = 0.4;
+
+/// How long a level has to take to load before the loading indicator is shown, in seconds.
+const LOADING_INDICATOR_DELAY: f64 = 0.25;
+
+/// How much the screen shakes for each point of damage the player takes, where 1 is the strongest shake.
+const SHAKE_PER_DAMAGE: f32 = 0.05;
+
+/// The level and spawn point that a warp is taking the player to.
+struct Destination {
+	level: GString,
+	spawn: GString
+}
+
+/// Where we are in the process of moving between levels. Player input is locked for the whole transition.
+#[derive(Default)]
+enum Transition {
+	#[default]
+	None,
+	FadingOut(Destination),
+	Loading { destination: Destination, level: SceneLoader, player: SceneLoader, elapsed: f64 },
+	FadingIn
+}
+
+/// Where the player's input comes from.
+#[derive(Default)]
+enum InputMode {
+	#[default]
+	Live,
+	Recording(InputRecording),
+	Replaying { recording: InputRecording, frame: usize }
+}
+
 impl LevelManager {
-	fn load_level(&mut self, packed_level: Gd<PackedScene>, packed_player: Gd<PackedScene>, spawn_point: Vector2, facing: IsometricFacing) -> Gd<Node> {
-		// Create the level.
-		let mut level : Gd<Node> = packed_level.instantiate().unwrap();
+	/// Load the first level, with the player at the exported coordinates.
+	fn start_level(&mut self) -> GameResult<()> {
+		let packed_level = load_scene(&self.level)?;
+		let packed_player = load_scene(&self.player)?;
+		let (level, player) = instantiate_level(&packed_level, &self.level, &packed_player, &self.player, &self.rng)?;
 		
-		// Create the player.
-		let mut player : Gd<Player> = packed_player.instantiate().unwrap().cast();
+		let level = self.load_level(level, player, self.player_coords, self.player_facing.clone());
+		self.current_level = Some(level);
+		Ok(())
+	}
+	
+	fn load_level(&mut self, mut level: Gd<Level>, mut player: Gd<Player>, spawn_point: Vector2, facing: IsometricFacing) -> Gd<Level> {
+		// Set up the level.
+		self.register_level_signals(&level);
+		
+		// Restore the level's state, if we've been here before.
+		self.current_level_path = self.level.clone();
+		if let Some(state) = self.level_states.get(&self.current_level_path.to_string()) {
+			level.bind_mut().restore_state(state);
+		}
+		
+		// Place the player.
 		player.set_position(spawn_point);
 		player.bind_mut().character.facing = facing;
 		
@@ -70,26 +249,117 @@ impl LevelManager {
 		
 		// Add the level to the scene tree.
 		self.base_mut().add_child(&level);
+		
+		// Jump the camera to the player, rather than sweeping across from wherever it was in the old level.
+		if let Some(mut camera) = self.camera() {
+			let bounds = level.bind().bounds();
+			camera.bind_mut().follow(player.upcast(), bounds);
+		}
 		level
 	}
 	
-	fn change_level(&mut self, packed_level: Gd<PackedScene>, packed_player: Gd<PackedScene>, spawn_point: Vector2, facing: IsometricFacing) {
-		let old_level = self.current_level.as_mut().unwrap();
+	/// Move the current level transition on, once whatever it is waiting for is finished.
+	fn process_transition(&mut self, delta: f64) {
+		if let Transition::None = &self.transition { return; }
 		
-		// Backup player data.
-		let old_player : Gd<Player> = old_level.get_node_as("Player");
-		let player_data = old_player.bind().data.clone();
+		// Without a screen fade, levels change without waiting for one.
+		let mut fade = self.ui::<ScreenFade>("FadeLayer/ScreenFade");
+		let mut indicator = self.ui::<Label>("FadeLayer/LoadingLabel");
+		let faded = fade.as_ref().is_none_or(|fade| fade.bind().is_done());
 		
-		// Delete old level.
-		old_level.queue_free();
-
-		// Create new level.
-		let new_level = self.load_level(packed_level, packed_player, spawn_point, facing);
+		self.transition = match std::mem::take(&mut self.transition) {
+			Transition::FadingOut(destination) if faded => {
+				// The screen is black, so start loading the new level in the background.
+				let level = SceneLoader::request(&destination.level);
+				let player = SceneLoader::request(&self.player);
+				Transition::Loading { destination, level, player, elapsed: 0.0 }
+			},
+			Transition::Loading { destination, mut level, mut player, elapsed } => {
+				let elapsed = elapsed + delta;
+				
+				match (level.poll(), player.poll()) {
+					(LoadStatus::Loaded(packed_level), LoadStatus::Loaded(packed_player)) => {
+						if let Some(indicator) = indicator.as_mut() { indicator.hide(); }
+						let context = format!("Can't warp to {}", destination.level);
+						self.finish_warp(destination, packed_level, packed_player).log_error(&context);
+						if let Some(fade) = fade.as_mut() { fade.bind_mut().fade_in(FADE_DURATION); }
+						Transition::FadingIn
+					},
+					(LoadStatus::Failed, _) | (_, LoadStatus::Failed) => {
+						// Stay where we are.
+						godot_error!("Failed to load {} or {}", level.path(), player.path());
+						if let Some(indicator) = indicator.as_mut() { indicator.hide(); }
+						if let Some(fade) = fade.as_mut() { fade.bind_mut().fade_in(FADE_DURATION); }
+						Transition::FadingIn
+					},
+					(level_status, player_status) => {
+						// Only bother showing the loading indicator for levels that take a while.
+						if elapsed >= LOADING_INDICATOR_DELAY && let Some(indicator) = indicator.as_mut() {
+							let progress = (load_progress(&level_status) + load_progress(&player_status)) / 2.0;
+							indicator.set_text(&format!("Loading... {}%", (progress * 100.0).round()));
+							indicator.show();
+						}
+						Transition::Loading { destination, level, player, elapsed }
+					}
+				}
+			},
+			Transition::FadingIn if faded => {
+				self.set_input_locked(false);
+				self.set_level_paused(false);
+				Transition::None
+			},
+			transition => transition
+		};
+	}
+	
+	/// Swap in the newly loaded level, once the screen has faded out. If the warp is broken, the player stays where they are.
+	fn finish_warp(&mut self, destination: Destination, packed_level: Gd<PackedScene>, packed_player: Gd<PackedScene>) -> GameResult<()> {
+		let (level, player) = instantiate_level(&packed_level, &destination.level, &packed_player, &self.player, &self.rng)?;
+		
+		// Work out where the player arrives.
+		let spawn_point = level.bind().find_spawn_point(&destination.spawn.to_string());
+		let (coords, facing) = match spawn_point {
+			Some(x) => x,
+			None => {
+				level.free();
+				player.free();
+				return Err(GameError::MissingSpawnPoint(destination.level.to_string(), destination.spawn.to_string()));
+			}
+		};
+		
+		self.level = destination.level;
+		self.player_coords = coords;
+		self.player_facing = facing;
 		
-		// Restore player data.
-		let mut new_player : Gd<Player> = new_level.get_node_as("Player");
-		new_player.bind_mut().data = player_data;
+		self.change_level(level, player, self.player_coords, self.player_facing.clone());
+		self.register_warp_signals();
+		
+		// Keep the new level still until the screen has faded back in.
+		self.set_input_locked(true);
+		self.set_level_paused(true);
+		
+		self.record_event(QuestEvent::Reached(self.level.to_string()));
+		self.save_game();
+		Ok(())
+	}
+	
+	fn change_level(&mut self, level: Gd<Level>, mut player: Gd<Player>, spawn_point: Vector2, facing: IsometricFacing) {
+		// Carry the player's data over to the new level.
+		if let Some(old_player) = self.current_player() {
+			player.bind_mut().data = old_player.bind().data.clone();
+		}
 		
+		if let Some(mut old_level) = self.current_level.take() {
+			// Backup level state.
+			let level_state = old_level.bind().save_state();
+			self.level_states.insert(self.current_level_path.to_string(), level_state);
+			
+			// Delete old level.
+			old_level.queue_free();
+		}
+		
+		// Create new level.
+		let new_level = self.load_level(level, player, spawn_point, facing);
 		self.current_level = Some(new_level);
 	}
 		
@@ -102,36 +372,470 @@ impl LevelManager {
 		}
 	}
 	
+	fn register_level_signals(&mut self, level: &Gd<Level>) {
+		level.signals().message().connect_other(self, Self::on_message);
+		level.signals().dialogue().connect_other(self, Self::on_dialogue);
+		level.signals().creature_died().connect_other(self, Self::on_creature_death);
+	}
+	
+	fn register_dialogue_signals(&mut self) {
+		if let Some(dialogue_box) = self.ui::<DialogueBox>("DialogueLayer/DialogueBox") {
+			dialogue_box.signals().choice_selected().connect_other(self, Self::on_dialogue_choice);
+		}
+	}
+	
 	fn register_death_signal(&mut self, player: &Gd<Player>) {
 		player.signals().dead().connect_other(self, Self::on_player_death);
+		player.signals().damaged().connect_other(self, Self::on_player_damaged);
+	}
+	
+	fn on_warp_entered(&mut self, body: Gd<Node2D>, level: GString, spawn: GString) {
+		if Faction::of(&body.upcast()) != Some(Faction::Player) { return; }
+		if !matches!(self.transition, Transition::None) { return; }
+		
+		let destination = Destination { level, spawn };
+		self.set_input_locked(true);
+		self.set_level_paused(true);
+		
+		if let Some(mut fade) = self.ui::<ScreenFade>("FadeLayer/ScreenFade") { fade.bind_mut().fade_out(FADE_DURATION); }
+		self.transition = Transition::FadingOut(destination);
+	}
+	
+	fn on_message(&mut self, text: GString) {
+		if let Some(mut msg) = self.ui::<MessageBox>("MessageLayer/MessageBox") { msg.bind_mut().update(text); }
+	}
+	
+	fn on_dialogue(&mut self, path: GString, speaker: GString) {
+		if self.dialogue.is_some() || self.choosing_perk { return; }
+		self.record_event(QuestEvent::Talked(speaker.to_string()));
+		
+		let dialogue = match load_dialogue(&path).log_error("Failed to start a conversation") {
+			Some(x) => x,
+			None => return
+		};
+		
+		let mut runner = DialogueRunner::new(dialogue);
+		let actions = runner.start();
+		self.dialogue = Some(runner);
+		self.apply_dialogue_actions(actions);
+		
+		// Stop the player from wandering off mid-conversation.
+		if let Some(mut player) = self.current_player() { player.bind_mut().input_locked = true; }
+		self.update_dialogue_box();
 	}
 	
-	fn on_warp_entered(&mut self, body: Gd<Node2D>, level: GString, coords: Vector2, facing: GString) {
-		if body.get_class().to_string().as_str() == "Player" {
-			self.level = level;
-			self.player_coords = coords;
-			self.player_facing = IsometricFacing::from_godot(facing);
-			self.warp = true;
+	/// Choices are made on the next physics frame, so that they can be recorded along with the rest of the input.
+	fn on_dialogue_choice(&mut self, index: i64) {
+		self.pending_choice = Some(index);
+	}
+	
+	fn choose_dialogue(&mut self, index: i64) {
+		if self.choosing_perk {
+			self.choose_perk(index);
+			return;
 		}
+		
+		let player = match self.current_player() {
+			Some(x) => x,
+			None => return
+		};
+		
+		let actions = match self.dialogue.as_mut() {
+			Some(runner) => {
+				let player = player.bind();
+				let context = DialogueScope { inventory: &player.data.inventory, state: &self.state };
+				runner.advance(usize::try_from(index).ok(), &context)
+			},
+			None => return
+		};
+		
+		self.apply_dialogue_actions(actions);
+		self.update_dialogue_box();
+	}
+	
+	/// Show the current line of dialogue, or close the dialogue box if the conversation is over.
+	fn update_dialogue_box(&mut self) {
+		let dialogue_box = self.ui::<DialogueBox>("DialogueLayer/DialogueBox");
+		let mut player = match self.current_player() {
+			Some(x) => x,
+			None => return
+		};
+		
+		let runner = match self.dialogue.as_ref() {
+			Some(x) => x,
+			None => return
+		};
+		
+		// The conversation is over once it's finished, or if there's nowhere to show it.
+		let mut dialogue_box = match dialogue_box {
+			Some(x) if !runner.is_finished() => x,
+			dialogue_box => {
+				if let Some(mut dialogue_box) = dialogue_box { dialogue_box.bind_mut().close(); }
+				player.bind_mut().input_locked = false;
+				self.dialogue = None;
+			
+				// Levelling up mid-conversation waits until it's over to offer a perk.
+				self.offer_perk();
+				return;
+			}
+		};
+		
+		let line = match runner.current_line() {
+			Some(x) => x,
+			None => return
+		};
+		
+		let choices : Vec<String> = {
+			let player = player.bind();
+			let context = DialogueScope { inventory: &player.data.inventory, state: &self.state };
+			runner.choices(&context).iter().map(|choice| choice.text.clone()).collect()
+		};
+		dialogue_box.bind_mut().display(&line.speaker, &line.text, &choices);
+	}
+	
+	fn apply_dialogue_actions(&mut self, actions: Vec<DialogueAction>) {
+		let mut player = match self.current_player() {
+			Some(x) => x,
+			None => return
+		};
+		
+		for action in actions {
+			match action {
+				DialogueAction::SetFlag(flag) => self.state.set_flag(&flag),
+				DialogueAction::ClearFlag(flag) => self.state.clear_flag(&flag),
+				DialogueAction::GiveItem(item, quantity) => player.bind_mut().data.inventory.add(&item, quantity),
+				DialogueAction::TakeItem(item, quantity) => { player.bind_mut().data.inventory.remove(&item, quantity); },
+				DialogueAction::StartQuest(id) => self.start_quest(&id)
+			}
+		}
+		
+		// Flags may have changed, which some quests could be waiting on.
+		self.update_quests();
+	}
+	
+	/// Part of the user interface. The game carries on without it if it's missing, but it's a mistake in the scene.
+	fn ui<T>(&self, path: &str) -> Option<Gd<T>>
+	where
+		T: GodotClass + Inherits<Node>
+	{
+		error::find_node(&self.base(), path).log_error("The game's user interface is broken")
+	}
+	
+	/// The camera is optional, so levels can be run without one.
+	fn camera(&self) -> Option<Gd<GameCamera>> {
+		self.base().try_get_node_as("Camera")
+	}
+	
+	fn current_player(&self) -> Option<Gd<Player>> {
+		self.current_level.as_ref()?.try_get_node_as("Player")
+	}
+	
+	/// Lock or unlock player input. Input stays locked while a conversation or the perk selection is open.
+	fn set_input_locked(&mut self, locked: bool) {
+		let locked = locked || self.dialogue.is_some() || self.choosing_perk;
+		if let Some(mut player) = self.current_player() { player.bind_mut().input_locked = locked; }
+	}
+	
+	/// Stop or restart everything in the current level, so nothing happens while the screen is faded out.
+	fn set_level_paused(&mut self, paused: bool) {
+		let mode = if paused { ProcessMode::DISABLED } else { ProcessMode::INHERIT };
+		if let Some(level) = self.current_level.as_mut() { level.set_process_mode(mode); }
+	}
+	
+	/// Fetch the input for this physics frame, from the keyboard or the recording being replayed.
+	fn next_input_frame(&mut self) -> Option<InputFrame> {
+		let frame = match &mut self.input {
+			InputMode::Live => InputFrame { choice: self.pending_choice.take(), ..InputFrame::poll() },
+			InputMode::Recording(recording) => {
+				let frame = InputFrame { choice: self.pending_choice.take(), ..InputFrame::poll() };
+				recording.push(frame.clone());
+				frame
+			},
+			InputMode::Replaying { recording, frame } => {
+				// Ignore anything the player clicks on while watching.
+				self.pending_choice = None;
+				
+				match recording.get(*frame) {
+					Some(x) => {
+						*frame += 1;
+						x.clone()
+					},
+					None => {
+						self.finish_replay();
+						return None;
+					}
+				}
+			}
+		};
+		Some(frame)
+	}
+	
+	/// Hand control back to the player once a replay runs out. Headless replays are regression tests, so quit instead.
+	fn finish_replay(&mut self) {
+		self.input = InputMode::Live;
+		
+		if DisplayServer::singleton().get_name() == "headless".into() {
+			let mut tree = self.base().get_tree().unwrap();
+			tree.quit();
+		}
+	}
+	
+	/// Pick up `--record=<path>` and `--replay=<path>` from the command line, after `--`.
+	fn read_command_line(&mut self) {
+		for arg in Os::singleton().get_cmdline_user_args().as_slice() {
+			let arg = arg.to_string();
+			if let Some(path) = arg.strip_prefix("--record=") { self.record_path = path.into(); }
+			if let Some(path) = arg.strip_prefix("--replay=") { self.replay_path = path.into(); }
+		}
+	}
+	
+	/// Start recording or replaying input, if either was asked for. Replays use the seed and starting level they were recorded with.
+	fn start_input(&mut self) {
+		if !self.replay_path.is_empty() {
+			let source = FileAccess::get_file_as_string(&self.replay_path);
+			let recording = InputRecording::parse(&source.to_string()).map_err(|e| GameError::Replay(self.replay_path.to_string(), e));
+			if let Some(recording) = recording.log_error("Failed to load the replay") {
+				self.rng.bind_mut().streams = RandomStreams::new(recording.seed);
+				if !recording.level.is_empty() { self.level = recording.level.as_str().into(); }
+				self.input = InputMode::Replaying { recording, frame: 0 };
+				return;
+			}
+		}
+		
+		if !self.record_path.is_empty() {
+			let seed = self.rng.bind().streams.seed();
+			self.input = InputMode::Recording(InputRecording::new(seed, &self.level.to_string()));
+		}
+	}
+	
+	fn on_creature_death(&mut self, kind: GString, experience: u32) {
+		self.record_event(QuestEvent::Killed(kind.to_string()));
+		self.award_experience(experience);
+	}
+	
+	fn award_experience(&mut self, amount: u32) {
+		if amount == 0 { return; }
+		let mut player = match self.current_player() {
+			Some(x) => x,
+			None => return
+		};
+		
+		let levels = player.bind_mut().gain_experience(amount);
+		if levels == 0 { return; }
+		
+		let level = player.bind().data.progression.level;
+		self.on_message(format!("You reached level {}!", level).into());
+		self.offer_perk();
+	}
+	
+	/// Show the perks to pick from in the dialogue box, if the player has earned one and isn't busy talking.
+	fn offer_perk(&mut self) {
+		if self.dialogue.is_some() || self.choosing_perk { return; }
+		let player = match self.current_player() {
+			Some(x) => x,
+			None => return
+		};
+		if player.bind().data.progression.unspent_perks == 0 { return; }
+		
+		let mut dialogue_box = match self.ui::<DialogueBox>("DialogueLayer/DialogueBox") {
+			Some(x) => x,
+			None => return
+		};
+		let choices : Vec<String> = Perk::ALL.iter().map(|perk| perk.describe()).collect();
+		dialogue_box.bind_mut().display("Level up", "Pick a perk.", &choices);
+		
+		self.choosing_perk = true;
+		self.set_input_locked(true);
+	}
+	
+	fn choose_perk(&mut self, index: i64) {
+		let perk = match usize::try_from(index).ok().and_then(|index| Perk::ALL.get(index)) {
+			Some(x) => *x,
+			None => return
+		};
+		
+		if let Some(mut player) = self.current_player() {
+			player.bind_mut().pick_perk(perk);
+		}
+		
+		if let Some(mut dialogue_box) = self.ui::<DialogueBox>("DialogueLayer/DialogueBox") { dialogue_box.bind_mut().close(); }
+		self.choosing_perk = false;
+		self.set_input_locked(false);
+		
+		// Gaining more than one level at once earns more than one perk.
+		self.offer_perk();
+	}
+	
+	fn on_player_damaged(&mut self, amount: f32) {
+		if let Some(mut camera) = self.camera() { camera.bind_mut().shake(amount * SHAKE_PER_DAMAGE); }
 	}
 	
 	fn on_player_death(&mut self) {
-		let mut msg : Gd<CanvasLayer> = self.base().get_node_as("DeathMessage");
-		msg.show();
+		if let Some(mut msg) = self.ui::<CanvasLayer>("DeathMessage") { msg.show(); }
+		self.record_event(QuestEvent::Died);
+	}
+	
+	fn load_quests(&mut self) {
+		if self.quests.is_empty() { return; }
+		
+		let source = FileAccess::get_file_as_string(&self.quests);
+		let book = QuestBook::parse(&source.to_string()).map_err(|e| GameError::Quest(self.quests.to_string(), e));
+		if let Some(book) = book.log_error("Failed to load quests") {
+			self.quest_book = book;
+		}
+	}
+	
+	fn start_quest(&mut self, id: &str) {
+		let quest = match self.quest_book.get(id) {
+			Some(x) => x,
+			None => {
+				godot_error!("Tried to start unknown quest {}", id);
+				return;
+			}
+		};
+		
+		if self.quest_log.is_started(id) { return; }
+		self.quest_log.start(quest, &self.state);
+		
+		let text = format!("Quest started: {}", quest.title);
+		self.on_message(text.into());
+		self.refresh_quest_log();
+	}
+	
+	/// Count an event towards the game state, and complete any quests that were waiting for it.
+	fn record_event(&mut self, event: QuestEvent) {
+		self.state.increment(&event.counter());
+		self.update_quests();
+	}
+	
+	fn update_quests(&mut self) {
+		let completed : Vec<(String, String, u32)> = self.quest_log.update(&self.quest_book, &self.state).iter()
+			.map(|quest| (quest.id.clone(), quest.title.clone(), quest.experience))
+			.collect();
+		
+		for (id, title, experience) in completed {
+			self.state.set_flag(&format!("{}_complete", id));
+			let text = format!("Quest complete: {}", title);
+			self.on_message(text.into());
+			self.award_experience(experience);
+		}
+		self.refresh_quest_log();
+	}
+	
+	fn refresh_quest_log(&mut self) {
+		let text = self.quest_log.describe(&self.quest_book, &self.state);
+		if let Some(mut panel) = self.ui::<QuestLogPanel>("QuestLayer/QuestLogPanel") { panel.bind_mut().set_text(&text); }
+	}
+	
+	/// Start the random number generator from the exported seed, or a new one if there isn't one.
+	fn seed_rng(&mut self) {
+		let seed = match self.seed {
+			0 => ((randi() as u64) << 32) | randi() as u64,
+			seed => seed as u64
+		};
+		self.rng.bind_mut().streams = RandomStreams::new(seed);
+	}
+	
+	/// Restore the game from the save file, if there is one. Returns the player data, which must be restored after the level is loaded.
+	fn load_game(&mut self) -> Option<PlayerData> {
+		if self.save_path.is_empty() { return None; }
+		
+		let save = SaveGame::read(&self.save_path)?;
+		self.level = save.level;
+		self.player_coords = save.coords;
+		self.player_facing = save.facing;
+		self.level_states = save.levels;
+		self.state = save.state;
+		self.quest_log = save.quests;
+		self.rng.bind_mut().streams = save.random;
+		Some(save.player)
+	}
+	
+	fn save_game(&mut self) {
+		if self.save_path.is_empty() { return; }
+		
+		let player = match self.current_player() {
+			Some(x) => x,
+			None => return
+		};
+		
+		// The level we're in only records its state when we leave it, so take a fresh copy.
+		let mut levels = self.level_states.clone();
+		if let Some(level) = &self.current_level {
+			levels.insert(self.current_level_path.to_string(), level.bind().save_state());
+		}
+		
+		let save = SaveGame {
+			level: self.level.clone(),
+			coords: self.player_coords,
+			facing: self.player_facing.clone(),
+			levels,
+			player: player.bind().data.clone(),
+			state: self.state.clone(),
+			quests: self.quest_log.clone(),
+			random: self.rng.bind().streams.clone()
+		};
+		
+		if let Err(e) = save.write(&self.save_path) {
+			godot_error!("Failed to save game to {}: {:?}", self.save_path, e);
+		}
+	}
+}
+
+fn load_scene(path: &GString) -> GameResult<Gd<PackedScene>> {
+	try_load(path).map_err(|_| GameError::LoadFailed(path.to_string()))
+}
+
+/// Create a level and a player to put in it, checking both scenes have the right root node.
+fn instantiate_level(packed_level: &Gd<PackedScene>, level_path: &GString, packed_player: &Gd<PackedScene>, player_path: &GString, rng: &Gd<GameRng>) -> GameResult<(Gd<Level>, Gd<Player>)> {
+	let mut level : Gd<Level> = error::instantiate(packed_level, level_path)?;
+	level.bind_mut().set_rng(rng.clone());
+	
+	// Generated levels must be filled in before their spawn points can be found.
+	let player = level.bind_mut().generate().and_then(|_| error::instantiate(packed_player, player_path));
+	match player {
+		Ok(player) => Ok((level, player)),
+		Err(e) => {
+			level.free();
+			Err(e)
+		}
 	}
 }
 
+fn load_dialogue(path: &GString) -> GameResult<Dialogue> {
+	let source = FileAccess::get_file_as_string(path);
+	Dialogue::parse(&source.to_string()).map_err(|e| GameError::Dialogue(path.to_string(), e))
+}
+
+/// How far along a scene is in loading, from 0 to 1.
+fn load_progress(status: &LoadStatus) -> f64 {
+	match status {
+		LoadStatus::InProgress(progress) => *progress,
+		LoadStatus::Loaded(_) => 1.0,
+		LoadStatus::Failed => 0.0
+	}
+}
+
+/// Lets dialogue conditions look at the player's inventory and the story flags.
+struct DialogueScope<'a> {
+	inventory: &'a Inventory,
+	state: &'a GameState
+}
+
+impl DialogueContext for DialogueScope<'_> {
+	fn has_item(&self, item: &str, quantity: u32) -> bool { self.inventory.count(item) >= quantity }
+	fn has_flag(&self, flag: &str) -> bool { self.state.has_flag(flag) }
+}
+
 #[derive(GodotClass)]
 #[class(base=Area2D,init)]
 pub struct LevelWarp {
 	#[export]
 	level: GString,
 	
+	/// Name of the spawn point in the destination level where the player arrives.
 	#[export]
-	coords: Vector2,
-	
-	#[export]
-	facing: IsometricFacing,
+	spawn: GString,
 
 	base: Base<Area2D>
 }
@@ -139,7 +843,7 @@ pub struct LevelWarp {
 #[godot_api]
 impl LevelWarp {
 	#[signal]
-	fn warp_entered(body: Gd<Node2D>, level: GString, coords: Vector2, facing: GString);
+	fn warp_entered(body: Gd<Node2D>, level: GString, spawn: GString);
 }
 
 #[godot_api]
@@ -153,10 +857,9 @@ impl IArea2D for LevelWarp {
 impl LevelWarp {
 	fn on_body_entered(&mut self, body: Gd<Node2D>) {
 		let level = self.level.clone();
-		let coords = self.coords.clone();
-		let facing = self.facing.clone();
+		let spawn = self.spawn.clone();
 	
 		let mut sig = self.signals().warp_entered();
-		sig.emit(&body, &level, coords, &facing.to_godot());
+		sig.emit(&body, &level, &spawn);
 	}
 }
@@ -1,12 +1,35 @@
 use godot::prelude::*;
 
+mod camera;
 mod character;
 mod control;
+mod creature;
+mod dialogue;
+mod error;
+mod faction;
+mod generate;
+mod harness;
+mod interact;
+mod inventory;
 mod level;
 mod level_manager;
+mod loader;
+mod loot;
+mod npc;
+mod occlusion;
+mod pickup;
 mod player;
+mod progression;
+mod quest;
+mod replay;
+mod reservation;
+mod rng;
+mod save;
+mod scenery;
+mod spatial;
+mod state;
 mod util;
-mod wolf;
+mod validate;
 
 struct MyExtension;
 
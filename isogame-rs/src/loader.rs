@@ -0,0 +1,64 @@
+use godot::prelude::*;
+use godot::classes::ResourceLoader;
+use godot::classes::PackedScene;
+
+/// The state of a scene being loaded in the background.
+pub enum LoadStatus {
+	InProgress(f64),	// Fraction loaded, from 0 to 1
+	Loaded(Gd<PackedScene>),
+	Failed
+}
+
+/// Loads a scene on a background thread through Godot's `ResourceLoader`.
+///
+/// The threaded loading methods are only generated by godot-rust behind the `experimental-threads` feature,
+/// so they are called dynamically instead. Only the main thread ever touches the result.
+pub struct SceneLoader {
+	path: GString,
+	scene: Option<Gd<PackedScene>>
+}
+
+// Values of ResourceLoader.ThreadLoadStatus.
+const THREAD_LOAD_IN_PROGRESS: i64 = 1;
+const THREAD_LOAD_LOADED: i64 = 3;
+
+impl SceneLoader {
+	/// Start loading a scene. Scenes that are already cached finish immediately.
+	pub fn request(path: &GString) -> Self {
+		let mut loader = ResourceLoader::singleton();
+		let args = [path.to_variant(), "PackedScene".to_variant()];
+		let error = loader.call("load_threaded_request", &args);
+		if error.try_to::<i64>().unwrap_or(0) != 0 {
+			godot_error!("Failed to start loading {}: error {}", path, error);
+		}
+		
+		Self { path: path.clone(), scene: None }
+	}
+	
+	/// Check on the scene. Once it has loaded, it is kept so that it can be polled again safely.
+	pub fn poll(&mut self) -> LoadStatus {
+		if let Some(scene) = &self.scene { return LoadStatus::Loaded(scene.clone()); }
+		
+		let mut loader = ResourceLoader::singleton();
+		let progress = VariantArray::new();
+		let args = [self.path.to_variant(), progress.to_variant()];
+		let status = loader.call("load_threaded_get_status", &args).try_to::<i64>().unwrap_or(0);
+		
+		match status {
+			THREAD_LOAD_IN_PROGRESS => LoadStatus::InProgress(progress.get(0).and_then(|x| x.try_to().ok()).unwrap_or(0.0)),
+			THREAD_LOAD_LOADED => {
+				let resource = loader.call("load_threaded_get", &[self.path.to_variant()]);
+				match resource.try_to::<Gd<PackedScene>>() {
+					Ok(scene) => {
+						self.scene = Some(scene.clone());
+						LoadStatus::Loaded(scene)
+					},
+					Err(_) => LoadStatus::Failed
+				}
+			},
+			_ => LoadStatus::Failed
+		}
+	}
+	
+	pub fn path(&self) -> &GString { &self.path }
+}
@@ -0,0 +1,225 @@
+use crate::error;
+use crate::error::LineError;
+use crate::rng::Rng;
+
+/// An item that can be dropped, and how many of it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LootDrop {
+	pub item: String,
+	pub min: u32,
+	pub max: u32
+}
+
+impl LootDrop {
+	fn roll(&self, rng: &mut Rng) -> u32 {
+		rng.range(self.min as i32, self.max as i32) as u32
+	}
+}
+
+/// What something drops when it dies. Guaranteed drops are always dropped, then the weighted drops are rolled for a
+/// number of times, where each roll might also come up with nothing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LootTable {
+	pub id: String,
+	pub guaranteed: Vec<LootDrop>,
+	pub weighted: Vec<(u32, LootDrop)>,		// Weight and drop
+	pub nothing: u32,						// Weight of dropping nothing on a roll
+	pub rolls: u32
+}
+
+impl LootTable {
+	fn new(id: &str) -> Self {
+		Self { id: id.to_string(), guaranteed: Vec::new(), weighted: Vec::new(), nothing: 0, rolls: 1 }
+	}
+	
+	/// Work out everything that gets dropped, with stacks of the same item merged together.
+	pub fn roll(&self, rng: &mut Rng) -> Vec<(String, u32)> {
+		let mut drops : Vec<(String, u32)> = Vec::new();
+		let mut add = |item: &str, quantity: u32| {
+			if quantity == 0 { return; }
+			match drops.iter_mut().find(|(other, _)| other == item) {
+				Some((_, total)) => *total += quantity,
+				None => drops.push((item.to_string(), quantity))
+			}
+		};
+		
+		for drop in self.guaranteed.iter() {
+			add(&drop.item, drop.roll(rng));
+		}
+		
+		let total = self.nothing + self.weighted.iter().map(|(weight, _)| weight).sum::<u32>();
+		if total == 0 { return drops; }
+		
+		for _ in 0..self.rolls {
+			let roll = rng.range(0, total as i32 - 1) as u32;
+			if let Some(drop) = self.pick(roll) { add(&drop.item, drop.roll(rng)); }
+		}
+		
+		drops
+	}
+	
+	/// Walk through the weighted drops until we pass the rolled weight. Whatever's left over is the chance of nothing.
+	fn pick(&self, mut roll: u32) -> Option<&LootDrop> {
+		for (weight, drop) in self.weighted.iter() {
+			if roll < *weight { return Some(drop); }
+			roll -= weight;
+		}
+		None
+	}
+}
+
+/// Every loot table in the game, parsed from a plain text file:
+///
+/// ```text
+/// [wolf]
+/// always: Wolf Pelt
+/// rolls: 2
+/// drop: 3 Coin x1-3
+/// drop: 1 Wolf Fang
+/// nothing: 2
+/// ```
+///
+/// Drops are given a weight first, then an item, then optionally a quantity or range of quantities.
+#[derive(Clone, Debug, Default)]
+pub struct LootBook {
+	tables: Vec<LootTable>
+}
+
+impl LootBook {
+	pub fn parse(source: &str) -> Result<Self, LineError> {
+		let tables = error::parse_sections(source, "loot table", "loot table id", |id, _| Ok(LootTable::new(id)), |table, line| {
+			let error = |message: &str| line.error(message);
+			let (key, value) = line.key_value()?;
+			
+			match key {
+				"always" => table.guaranteed.push(parse_drop(value).ok_or_else(|| error("invalid drop"))?),
+				"drop" => {
+					let (weight, drop) = value.split_once(' ').ok_or_else(|| error("expected 'drop: weight item'"))?;
+					let weight = weight.parse().map_err(|_| error("invalid weight"))?;
+					let drop = parse_drop(drop.trim()).ok_or_else(|| error("invalid drop"))?;
+					table.weighted.push((weight, drop));
+				},
+				"nothing" => table.nothing = value.parse().map_err(|_| error("invalid weight"))?,
+				"rolls" => table.rolls = value.parse().map_err(|_| error("invalid number of rolls"))?,
+				_ => return Err(error("unknown property"))
+			}
+			Ok(())
+		})?;
+		
+		Ok(Self { tables })
+	}
+	
+	pub fn get(&self, id: &str) -> Option<&LootTable> {
+		self.tables.iter().find(|table| table.id == id)
+	}
+}
+
+/// Parse "Coin x1-3", "Coin x2" or just "Coin".
+fn parse_drop(value: &str) -> Option<LootDrop> {
+	let (item, min, max) = match value.rsplit_once(' ') {
+		Some((item, quantity)) if quantity.starts_with('x') => {
+			let quantity = &quantity[1..];
+			let (min, max) = quantity.split_once('-').unwrap_or((quantity, quantity));
+			(item.trim(), min.parse().ok()?, max.parse().ok()?)
+		},
+		_ => (value, 1, 1)
+	};
+	
+	if item.is_empty() || min > max { return None; }
+	Some(LootDrop { item: item.to_string(), min, max })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	const SOURCE: &str = "
+		[wolf]
+		always: Wolf Pelt
+		rolls: 2
+		drop: 3 Coin x1-3
+		drop: 1 Wolf Fang
+		nothing: 2
+		
+		[empty]
+		nothing: 1
+	";
+	
+	fn book() -> LootBook {
+		LootBook::parse(SOURCE).unwrap()
+	}
+	
+	fn quantity(drops: &[(String, u32)], item: &str) -> u32 {
+		drops.iter().find(|(other, _)| other == item).map_or(0, |(_, quantity)| *quantity)
+	}
+	
+	#[test]
+	fn guaranteed_drops_always_appear() {
+		let book = book();
+		let wolf = book.get("wolf").unwrap();
+		let mut rng = Rng::new(3);
+		for _ in 0..200 {
+			assert_eq!(quantity(&wolf.roll(&mut rng), "Wolf Pelt"), 1);
+		}
+	}
+	
+	#[test]
+	fn weight_boundaries() {
+		let book = book();
+		let wolf = book.get("wolf").unwrap();
+		let item = |roll: u32| wolf.pick(roll).map(|drop| drop.item.as_str());
+		assert_eq!(item(0), Some("Coin"));
+		assert_eq!(item(2), Some("Coin"));
+		assert_eq!(item(3), Some("Wolf Fang"));
+		assert_eq!(item(4), None);
+		assert_eq!(item(5), None);
+	}
+	
+	#[test]
+	fn rolls_stay_in_range() {
+		let book = book();
+		let mut rng = Rng::new(11);
+		
+		// A table that can only roll nothing never drops anything, and one with no chance of nothing always drops something.
+		let empty = book.get("empty").unwrap();
+		let mut always = book.get("wolf").unwrap().clone();
+		always.guaranteed.clear();
+		always.nothing = 0;
+		always.rolls = 1;
+		
+		let mut seen = [false; 2];
+		for _ in 0..500 {
+			assert!(empty.roll(&mut rng).is_empty());
+			
+			let drops = always.roll(&mut rng);
+			assert_eq!(drops.len(), 1);
+			seen[0] |= quantity(&drops, "Coin") > 0;
+			seen[1] |= quantity(&drops, "Wolf Fang") > 0;
+			assert!(quantity(&drops, "Coin") <= 3);
+		}
+		assert_eq!(seen, [true, true]);
+	}
+	
+	#[test]
+	fn quantities() {
+		assert_eq!(parse_drop("Coin x1-3"), Some(LootDrop { item: "Coin".to_string(), min: 1, max: 3 }));
+		assert_eq!(parse_drop("Coin x2"), Some(LootDrop { item: "Coin".to_string(), min: 2, max: 2 }));
+		assert_eq!(parse_drop("Wolf Fang"), Some(LootDrop { item: "Wolf Fang".to_string(), min: 1, max: 1 }));
+		assert_eq!(parse_drop("Coin x3-1"), None);
+		assert_eq!(parse_drop("Coin xmany"), None);
+		
+		let error = LootBook::parse("[wolf]\ndrop: 1 Coin x3-1").unwrap_err();
+		assert_eq!(error, LineError::new(2, "invalid drop"));
+	}
+	
+	#[test]
+	fn same_seed_same_drops() {
+		let book = book();
+		let wolf = book.get("wolf").unwrap();
+		let roll = |seed: u64| {
+			let mut rng = Rng::new(seed);
+			(0..20).map(|_| wolf.roll(&mut rng)).collect::<Vec<_>>()
+		};
+		assert_eq!(roll(42), roll(42));
+	}
+}
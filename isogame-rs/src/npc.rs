@@ -0,0 +1,156 @@
+use godot::prelude::*;
+use godot::classes::CharacterBody2D;
+use godot::classes::ICharacterBody2D;
+use godot::classes::AnimatedSprite2D;
+
+use crate::error::LogError;
+use crate::character::MovementState;
+use crate::character::MovementUpdate;
+use crate::character::MovingCharacter;
+use crate::faction::Faction;
+use crate::rng;
+use crate::rng::GameRng;
+use crate::util::IsometricFacing;
+
+/// A non-hostile character who stands or wanders around, and can be talked to.
+#[derive(GodotClass)]
+#[class(base=CharacterBody2D)]
+pub struct Npc {
+	/// Path to the dialogue file used when the player talks to this character.
+	#[export]
+	pub dialogue: GString,
+	
+	/// Whether to wander around at random, or stand still.
+	#[export]
+	pub wander: bool,
+	
+	#[export]
+	pub speed: f32,
+	
+	/// Which side the character is on. Townsfolk are ignored by everything, and ignore everything back.
+	#[export]
+	pub faction: Faction,
+	
+	pub character: MovingCharacter,
+	pub input_delay: f64,
+	pub rng: Option<Gd<GameRng>>,
+	pub animation_state: NpcAnimationState,
+	base: Base<CharacterBody2D>
+}
+
+#[godot_api]
+impl Npc {
+	#[signal]
+	pub fn update_nav(instance: Gd<Npc>);
+}
+
+#[godot_api]
+impl ICharacterBody2D for Npc {
+	fn init(base: Base<CharacterBody2D>) -> Self {
+		Self {
+			dialogue: GString::new(),
+			wander: false,
+			speed: 1.5,
+			faction: Faction::Townsfolk,
+			character: MovingCharacter::default(),
+			input_delay: 0.00,
+			rng: None,
+			animation_state: NpcAnimationState::Idle,
+			base
+		}
+	}
+	
+	fn ready(&mut self) {
+		// Play sprite animation.
+		let mut sprite : Gd<AnimatedSprite2D> = self.base().get_node_as("AnimatedSprite2D");
+		sprite.play();
+		
+		// Add to the entities group, and our faction's group.
+		self.base_mut().add_to_group("entities");
+		let faction = self.faction.group();
+		self.base_mut().add_to_group(faction);
+	}
+	
+	fn physics_process(&mut self, delta: f64) {
+		if !self.character.has_nav() {
+			// If we don't have pathfinding data, request it and wait.
+			self.ask_for_nav();
+			return;
+		}
+		
+		// Wandering logic.
+		if self.input_delay > 0.00 {
+			self.input_delay -= delta;
+		} else if self.wander {
+			self.handle_wandering();
+		}
+		
+		// Movement logic.
+		let position = self.base().get_position();
+		match self.character.update_movement(position, self.speed, delta) {
+			MovementUpdate::None | MovementUpdate::Blocked => {
+				if self.input_delay <= 0.0 { self.animation_state = NpcAnimationState::Idle; }
+			},
+			MovementUpdate::Started => self.animation_state = NpcAnimationState::Walking,
+			MovementUpdate::Moved(position) | MovementUpdate::Arrived(position) => self.base_mut().set_position(position)
+		};
+		
+		// Animation logic.
+		let mut sprite : Gd<AnimatedSprite2D> = self.base().get_node_as("AnimatedSprite2D");
+		
+		match &self.animation_state {
+			NpcAnimationState::Idle => sprite.set_animation(&self.character.facing.get_animation("idle")),
+			NpcAnimationState::Walking => sprite.set_animation(&self.character.facing.get_animation("walk"))
+		}
+	}
+}
+
+impl Npc {
+	/// Turn to face whoever is talking to us, and stop wandering for a while. Returns the path to our dialogue.
+	pub fn talk(&mut self, speaker_tile: Vector2i) -> GString {
+		if self.character.movement == MovementState::Idle {
+			let position = self.base().get_position();
+			self.character.face_tile(position, speaker_tile).log_error("Npc couldn't face the speaker");
+		}
+		self.input_delay = 5.0;
+		self.dialogue.clone()
+	}
+	
+	/// Occasionally turn or take a step in a random direction.
+	fn handle_wandering(&mut self) {
+		let mut rng = match self.rng.clone() {
+			Some(x) => x,
+			None => return
+		};
+		let mut rng = rng.bind_mut();
+		let rng = rng.stream(rng::stream::WANDER);
+		
+		if self.character.movement == MovementState::Idle {
+			let facing = match rng.range(0, 3) {
+				0 => IsometricFacing::NW,
+				1 => IsometricFacing::NE,
+				2 => IsometricFacing::SW,
+				_ => IsometricFacing::SE
+			};
+			
+			// Either change facing or move, but not both.
+			if self.character.facing == facing {
+				self.character.movement = MovementState::StartMoving;
+			} else {
+				self.character.facing = facing;
+			}
+			self.input_delay = rng.range_f64(1.0, 4.0);
+		}
+	}
+	
+	fn ask_for_nav(&mut self) {
+		let gd = self.to_gd();
+		let mut sig = self.signals().update_nav();
+		sig.emit(&gd);
+	}
+}
+
+pub enum NpcAnimationState {
+	Idle,
+	Walking
+}
@@ -0,0 +1,94 @@
+use std::collections::HashSet;
+
+use godot::prelude::*;
+use godot::classes::TileMapLayer;
+use godot::classes::ITileMapLayer;
+use godot::classes::TileData;
+
+/// How opaque walls and tall scenery are while something is behind them, from 0 (invisible) to 1 (not faded at all).
+pub const FADED_ALPHA: f32 = 0.35;
+
+/// A tile layer for walls, which can fade individual tiles out when they're hiding something behind them.
+#[derive(GodotClass)]
+#[class(base=TileMapLayer)]
+pub struct WallLayer {
+	/// How opaque faded walls are, from 0 (invisible) to 1 (not faded at all).
+	#[export]
+	faded_alpha: f32,
+	
+	faded: HashSet<Vector2i>,
+	base: Base<TileMapLayer>
+}
+
+#[godot_api]
+impl ITileMapLayer for WallLayer {
+	fn init(base: Base<TileMapLayer>) -> Self {
+		Self {
+			faded_alpha: FADED_ALPHA,
+			faded: HashSet::new(),
+			base
+		}
+	}
+	
+	fn use_tile_data_runtime_update(&mut self, coords: Vector2i) -> bool {
+		self.faded.contains(&coords)
+	}
+	
+	fn tile_data_runtime_update(&mut self, _coords: Vector2i, tile_data: Option<Gd<TileData>>) {
+		if let Some(mut tile_data) = tile_data {
+			let mut colour = tile_data.get_modulate();
+			colour.a = self.faded_alpha;
+			tile_data.set_modulate(colour);
+		}
+	}
+}
+
+impl WallLayer {
+	/// Fade exactly these tiles, and no others.
+	pub fn set_faded(&mut self, tiles: HashSet<Vector2i>) {
+		if tiles == self.faded { return; }
+		self.faded = tiles;
+		self.base_mut().notify_runtime_tile_data_update();
+	}
+}
+
+/// Every tile whose contents would be drawn over a character standing on `tile`, up to `depth` rows in front of it.
+///
+/// On our isometric grid, tiles further down the screen have a larger `y - x`, and tiles in the same screen column have the same `x + y`.
+/// Anything in front of the character and no more than one column to either side overlaps it.
+pub fn occluding_tiles(tile: Vector2i, depth: i32) -> impl Iterator<Item = Vector2i> {
+	(1..=depth).flat_map(move |row| {
+		(-1..=1).filter(move |column| (row + column) % 2 == 0).map(move |column| {
+			// Solve for the offset with `dy - dx = row` and `dx + dy = column`.
+			let dy = (row + column) / 2;
+			let dx = (column - row) / 2;
+			tile + Vector2i::new(dx, dy)
+		})
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	#[test]
+	fn occluding_tiles_in_front() {
+		let tile = Vector2i::new(5, 5);
+		let tiles : Vec<Vector2i> = occluding_tiles(tile, 1).collect();
+		assert_eq!(tiles, vec![Vector2i::new(4, 5), Vector2i::new(5, 6)]);
+		
+		let tiles : Vec<Vector2i> = occluding_tiles(tile, 3).collect();
+		assert_eq!(tiles.len(), 5);
+		for occluder in tiles {
+			let offset = occluder - tile;
+			// Further down the screen, and no more than one column to either side.
+			assert!((1..=3).contains(&(offset.y - offset.x)));
+			assert!((offset.x + offset.y).abs() <= 1);
+		}
+	}
+	
+	#[test]
+	fn nothing_occludes_with_no_depth() {
+		assert_eq!(occluding_tiles(Vector2i::ZERO, 0).count(), 0);
+	}
+}
@@ -0,0 +1,50 @@
+use godot::prelude::*;
+use godot::classes::Area2D;
+use godot::classes::IArea2D;
+
+use crate::player::Player;
+
+/// An item lying on the ground, which the player picks up by walking onto it.
+#[derive(GodotClass)]
+#[class(base=Area2D,init)]
+pub struct Pickup {
+	#[export]
+	pub item: GString,
+	
+	#[export]
+	#[init(val = 1)]
+	pub quantity: u32,
+	
+	base: Base<Area2D>
+}
+
+#[godot_api]
+impl Pickup {
+	#[signal]
+	pub fn picked_up(item: GString, quantity: u32);
+}
+
+#[godot_api]
+impl IArea2D for Pickup {
+	fn ready(&mut self) {
+		self.base_mut().add_to_group("pickups");
+		
+		let sig = self.signals().body_entered();
+		sig.connect_self(Self::on_body_entered);
+	}
+}
+
+impl Pickup {
+	fn on_body_entered(&mut self, body: Gd<Node2D>) {
+		if let Ok(mut player) = body.try_cast::<Player>() {
+			player.bind_mut().data.inventory.add(&self.item.to_string(), self.quantity);
+			
+			let item = self.item.clone();
+			let quantity = self.quantity;
+			let mut sig = self.signals().picked_up();
+			sig.emit(&item, quantity);
+			
+			self.base_mut().queue_free();
+		}
+	}
+}
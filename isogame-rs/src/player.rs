@@ -3,10 +3,29 @@ use godot::classes::CharacterBody2D;
 use godot::classes::ICharacterBody2D;
 use godot::classes::AnimatedSprite2D;
 
-use crate::level;
+use crate::character::MovementState;
+use crate::character::MovementUpdate;
 use crate::character::MovingCharacter;
 use crate::control::HealthBar;
-use crate::util::KeyboardInput;
+use crate::control::StaminaBar;
+use crate::faction::Faction;
+use crate::inventory::Inventory;
+use crate::progression::Perk;
+use crate::progression::Progression;
+use crate::replay::InputFrame;
+
+/// How much faster than walking the player moves while sprinting.
+const SPRINT_MULTIPLIER: f32 = 1.6;
+/// Stamina used up per second of sprinting.
+const STAMINA_DRAIN: f32 = 30.0;
+/// Stamina recovered per second while walking or standing still.
+const STAMINA_REGEN: f32 = 15.0;
+/// Once stamina runs out, how much of it has to come back (as a fraction of the maximum) before the player can sprint again.
+const STAMINA_RECOVERY: f32 = 0.25;
+/// How much each level gained raises maximum health, speed and damage by.
+const HEALTH_PER_LEVEL: f32 = 10.0;
+const SPEED_PER_LEVEL: f32 = 0.05;
+const DAMAGE_PER_LEVEL: f32 = 2.0;
 
 #[derive(GodotClass)]
 #[class(base=CharacterBody2D)]
@@ -14,22 +33,28 @@ pub struct Player {
 	pub data: PlayerData,
 	pub character: MovingCharacter,
 	pub input_delay: f64,
-	pub movement_state: PlayerMovementState,
+	pub input_locked: bool,
+	pub sprinting: bool,
+	/// Set when stamina runs out, until enough of it has recovered.
+	pub exhausted: bool,
+	/// Input for the next physics frame, from the level manager. Read straight from the keyboard if it isn't set.
+	pub input: Option<InputFrame>,
 	pub animation_state: PlayerAnimationState,
-	pub reservation_state: PlayerReservationState,
 	base: Base<CharacterBody2D>
 }
 
 #[godot_api]
 impl Player {
 	#[signal]
-	pub fn reserve_tile(coords: Vector2i);
+	pub fn update_nav(instance: Gd<Player>);
 	#[signal]
-	pub fn unreserve_tile(coords: Vector2i);
+	pub fn interact(instance: Gd<Player>, coords: Vector2i);
 	#[signal]
-	pub fn update_nav(instance: Gd<Player>);
+	pub fn push(coords: Vector2i, facing: GString);
 	#[signal]
 	pub fn dead();
+	#[signal]
+	pub fn damaged(amount: f32);
 }
 
 #[godot_api]
@@ -39,9 +64,11 @@ impl ICharacterBody2D for Player {
 			data: PlayerData::default(),
 			character: MovingCharacter::default(),
 			input_delay: 0.00,
-			movement_state: PlayerMovementState::Idle,
+			input_locked: false,
+			sprinting: false,
+			exhausted: false,
+			input: None,
 			animation_state: PlayerAnimationState::Idle,
-			reservation_state: PlayerReservationState::ReserveLocation,
 			base
 		}
 	}
@@ -51,67 +78,58 @@ impl ICharacterBody2D for Player {
 		let mut sprite : Gd<AnimatedSprite2D> = self.base().get_node_as("AnimatedSprite2D");
 		sprite.play();
 		
-		// Add to the entities group.
+		// Add to the entities group, and the player's faction.
 		self.base_mut().add_to_group("entities");
+		self.base_mut().add_to_group(Faction::Player.group());
 	}
 	
-	fn process(&mut self, delta: f64) {
+	fn physics_process(&mut self, delta: f64) {
 		if !self.character.has_nav() {
 			// If we don't have pathfinding data, request it and wait.
 			self.ask_for_nav();
 			return;
 		}
-	
-		// Input logic.
-		if self.input_delay > 0.00 {
-			self.input_delay -= delta;
-		} else {
-			self.handle_input();
-		}
 		
-		// Death logic
+		// Death logic. This comes first, so a dying player never starts a step, and gives up the tile they held.
 		if self.data.health <= 0.00 {
+			let position = self.base().get_position();
+			self.character.leave(position);
 			self.base_mut().hide();
 			
 			let mut sig = self.signals().dead();
 			sig.emit();
 			
 			self.base_mut().queue_free();
-		}
-	}
-	
-	fn physics_process(&mut self, delta: f64) {
-		if !self.character.has_nav() {
-			// If we don't have pathfinding data, request it and wait.
-			self.ask_for_nav();
 			return;
 		}
 		
+		// Input logic. This runs on physics frames, so that recorded input replays exactly the same way.
+		let input = self.input.take().unwrap_or_else(InputFrame::poll);
+		let held = if self.input_locked { None } else { input.movement.clone() };
+		let sprint = !self.input_locked && input.sprint;
+		if self.input_delay > 0.00 {
+			self.input_delay -= delta;
+		} else {
+			self.handle_input(input);
+		}
+		
+		// Stamina logic.
+		self.update_stamina(sprint, delta);
+		
 		// Movement logic.
-		match &self.movement_state {
-			PlayerMovementState::Idle => {
+		let position = self.base().get_position();
+		match self.character.update_movement(position, self.current_speed(), delta) {
+			MovementUpdate::None => {
 				if self.input_delay <= 0.0 { self.animation_state = PlayerAnimationState::Idle; }
 			},
-			PlayerMovementState::StartMoving => {
-				let position = self.base().get_position();
-				if self.character.try_moving(position) {
-					self.movement_state = PlayerMovementState::Moving;
-					self.animation_state = PlayerAnimationState::Walking;
-					self.reservation_state = PlayerReservationState::ReserveDestination;
-				} else {
-					self.movement_state = PlayerMovementState::Idle;
-				}
-			},
-			PlayerMovementState::Moving => {
-				// Keep moving.
-				let position = self.base().get_position();
-				let new_position = self.character.keep_moving(position, self.data.speed, delta);
-				self.base_mut().set_position(new_position);
+			MovementUpdate::Started => self.animation_state = self.moving_animation(),
+			MovementUpdate::Blocked => self.push_facing_tile(), // If something is in the way, try to push it.
+			MovementUpdate::Moved(position) => self.base_mut().set_position(position),
+			MovementUpdate::Arrived(position) => {
+				self.base_mut().set_position(position);
 				
-				if let None = self.character.destination {
-					// If we're done moving, change to the idle state.
-					self.movement_state = PlayerMovementState::Idle;
-				}
+				// If the same direction is still held, carry straight on into the next step without stopping.
+				if held == Some(self.character.facing.clone()) { self.start_moving(); }
 			}
 		};
 		
@@ -120,113 +138,197 @@ impl ICharacterBody2D for Player {
 		
 		match &self.animation_state {
 			PlayerAnimationState::Idle => sprite.set_animation(&self.character.facing.get_animation("idle")),
-			PlayerAnimationState::Walking => sprite.set_animation(&self.character.facing.get_animation("walk"))
-		}
-		
-		// Reservation logic.
-		match &self.reservation_state {
-			PlayerReservationState::None => (),
-			PlayerReservationState::ReserveLocation => {
-				self.reserve_current_tile();
-				self.reservation_state = PlayerReservationState::None;
-			},
-			PlayerReservationState::ReserveDestination => {
-				self.reserve_facing_tile();
-				self.unreserve_current_tile();
-				self.reservation_state = PlayerReservationState::None;
-			}
+			PlayerAnimationState::Walking => sprite.set_animation(&self.character.facing.get_animation("walk")),
+			PlayerAnimationState::Running => sprite.set_animation(&self.character.facing.get_animation("run"))
 		}
 	}
 }
 
 impl Player {
-	fn handle_input(&mut self) {
-		if let Some(facing) = KeyboardInput::get_movement() {
-			if let PlayerMovementState::Idle = &self.movement_state {
-				// Either change facing or move, but not both.
-				if self.character.facing != facing {
-					self.character.facing = facing;
-					self.input_delay = 0.1; // small input delay to prevent accidental movement
-				} else {
-					self.movement_state = PlayerMovementState::StartMoving;
-					self.input_delay = 0.2; // larger input delay when moving
-				}
+	fn handle_input(&mut self, input: InputFrame) {
+		if self.input_locked { return; }
+		
+		if input.interact && self.character.movement == MovementState::Idle {
+			self.interact_facing_tile();
+			return;
+		}
+		
+		if let Some(facing) = input.movement && self.character.movement == MovementState::Idle {
+			// Either change facing or move, but not both.
+			if self.character.facing != facing {
+				self.character.facing = facing;
+				self.input_delay = 0.1; // small input delay to prevent accidental movement
+			} else {
+				self.character.movement = MovementState::StartMoving;
+				self.input_delay = 0.2; // larger input delay when moving
 			}
 		}
 	}
 
+	/// Step onto the tile we're facing, or push whatever is in the way.
+	fn start_moving(&mut self) {
+		let position = self.base().get_position();
+		if self.character.start_moving(position) {
+			self.animation_state = self.moving_animation();
+		} else {
+			// If something is in the way, try to push it.
+			self.push_facing_tile();
+		}
+	}
+	
+	/// Sprint while the sprint key is held and we're moving, using up stamina, and recover it the rest of the time.
+	fn update_stamina(&mut self, sprint: bool, delta: f64) {
+		let delta = delta as f32;
+		let moving = self.character.movement == MovementState::Moving;
+		let previous = self.data.stamina;
+		
+		self.sprinting = sprint && moving && !self.exhausted && self.data.stamina > 0.0;
+		if self.sprinting {
+			self.data.stamina = f32::max(0.0, self.data.stamina - STAMINA_DRAIN * delta);
+			if self.data.stamina == 0.0 { self.exhausted = true; }
+		} else {
+			self.data.stamina = f32::min(self.data.max_stamina, self.data.stamina + STAMINA_REGEN * delta);
+			if self.data.stamina >= self.data.max_stamina * STAMINA_RECOVERY { self.exhausted = false; }
+		}
+		
+		// Speeding up or slowing down part way through a step switches the animation straight away.
+		if moving { self.animation_state = self.moving_animation(); }
+		
+		if self.data.stamina != previous {
+			let percentage = self.data.stamina / self.data.max_stamina * 100.0;
+			let mut staminabar : Gd<StaminaBar> = self.base().get_node_as("StaminaBar");
+			staminabar.bind_mut().update(percentage as f64);
+		}
+	}
+	
+	/// How fast we're moving, in tiles per second.
+	fn current_speed(&self) -> f32 {
+		if self.sprinting { self.data.speed * SPRINT_MULTIPLIER } else { self.data.speed }
+	}
+	
+	fn moving_animation(&self) -> PlayerAnimationState {
+		if self.sprinting { PlayerAnimationState::Running } else { PlayerAnimationState::Walking }
+	}
+	
 	fn ask_for_nav(&mut self) {
 		let gd = self.to_gd();
 		let mut sig = self.signals().update_nav();
 		sig.emit(&gd);
 	}
 
-	fn reserve_facing_tile(&mut self) {
+	fn interact_facing_tile(&mut self) {
 		if !self.character.has_nav() { return; }
 		
+		let gd = self.to_gd();
 		let gridpos = self.character.calculate_movement_grid(self.base().get_position());
 		
-		let mut sig = self.signals().reserve_tile();
-		sig.emit(gridpos);
+		let mut sig = self.signals().interact();
+		sig.emit(&gd, gridpos);
 	}
-	
-	fn reserve_current_tile(&mut self) {
+
+	fn push_facing_tile(&mut self) {
 		if !self.character.has_nav() { return; }
-	
-		let tilemap = self.character.tilemap.as_ref().unwrap();
-		let pos = self.base().get_position();
-		let gridpos = level::global_to_grid(&tilemap, pos);
 		
-		let mut sig = self.signals().reserve_tile();
-		sig.emit(gridpos);
-	}
-	
-	fn unreserve_current_tile(&mut self) {
-		if !self.character.has_nav() { return; } 
-		let tilemap = self.character.tilemap.as_ref().unwrap();
-		let pos = self.base().get_position();
-		let gridpos = level::global_to_grid(&tilemap, pos);
+		let gridpos = self.character.calculate_movement_grid(self.base().get_position());
+		let facing = self.character.facing.to_godot();
 		
-		let mut sig = self.signals().unreserve_tile();
-		sig.emit(gridpos);
+		let mut sig = self.signals().push();
+		sig.emit(gridpos, &facing);
 	}
-	
+
 	pub fn damage(&mut self, damage: f32) {
 		self.data.health -= damage;
+		self.update_health_bar();
 		
+		let mut sig = self.signals().damaged();
+		sig.emit(damage);
+	}
+	
+	/// Earn some experience, showing off any health gained by levelling up. Returns how many levels were gained.
+	pub fn gain_experience(&mut self, amount: u32) -> u32 {
+		let levels = self.data.gain_experience(amount);
+		if levels > 0 { self.update_health_bar(); }
+		levels
+	}
+	
+	/// Pick one of the perks earned by levelling up. Returns false if there aren't any to pick.
+	pub fn pick_perk(&mut self, perk: Perk) -> bool {
+		let picked = self.data.pick_perk(perk);
+		if picked { self.update_health_bar(); }
+		picked
+	}
+	
+	fn update_health_bar(&mut self) {
+		let percentage = self.data.health / self.data.max_health * 100.0;
 		let mut healthbar : Gd<HealthBar> = self.base().get_node_as("HealthBar");
-		healthbar.bind_mut().update(self.data.health as f64);
+		healthbar.bind_mut().update(percentage as f64);
 	}
 }
 
 #[derive(Clone)]
 pub struct PlayerData {
-	speed: f32,
-	health: f32
+	pub speed: f32,
+	pub health: f32,
+	pub max_health: f32,
+	pub stamina: f32,
+	pub max_stamina: f32,
+	pub damage: f32,
+	pub inventory: Inventory,
+	pub progression: Progression
 }
 
 impl Default for PlayerData {
 	fn default() -> Self {
 		Self {
 			speed: 2.5,
-			health: 100.0
+			health: 100.0,
+			max_health: 100.0,
+			stamina: 100.0,
+			max_stamina: 100.0,
+			damage: 25.0,
+			inventory: Inventory::default(),
+			progression: Progression::default()
 		}
 	}
 }
 
-pub enum PlayerMovementState {
-	Idle,
-	StartMoving,
-	Moving
+impl PlayerData {
+	/// Earn some experience, getting stronger for every level gained. Returns how many levels were gained.
+	pub fn gain_experience(&mut self, amount: u32) -> u32 {
+		let levels = self.progression.gain(amount);
+		
+		// Extra maximum health comes with the health to fill it.
+		let bonus = levels as f32;
+		self.max_health += HEALTH_PER_LEVEL * bonus;
+		self.health += HEALTH_PER_LEVEL * bonus;
+		self.speed += SPEED_PER_LEVEL * bonus;
+		self.damage += DAMAGE_PER_LEVEL * bonus;
+		levels
+	}
+	
+	/// Pick one of the perks earned by levelling up. Returns false if there aren't any to pick.
+	pub fn pick_perk(&mut self, perk: Perk) -> bool {
+		if !self.progression.pick(perk) { return false; }
+		
+		let amount = perk.amount();
+		match perk {
+			Perk::Toughness => {
+				self.max_health += amount;
+				self.health += amount;
+			},
+			Perk::Swiftness => self.speed += amount,
+			Perk::Ferocity => self.damage += amount,
+			Perk::Endurance => {
+				self.max_stamina += amount;
+				self.stamina += amount;
+			}
+		}
+		true
+	}
 }
 
 pub enum PlayerAnimationState {
 	Idle,
-	Walking
-}
-
-pub enum PlayerReservationState {
-	None,
-	ReserveLocation,
-	ReserveDestination
+	Walking,
+	Running
 }
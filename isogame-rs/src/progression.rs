@@ -0,0 +1,128 @@
+use godot::prelude::*;
+
+/// A permanent bonus the player picks each time they level up.
+#[derive(GodotConvert, Clone, Copy, Debug, PartialEq)]
+#[godot(via = GString)]
+pub enum Perk {
+	Toughness,		// More maximum health
+	Swiftness,		// Faster movement
+	Ferocity,		// More damage
+	Endurance		// More maximum stamina
+}
+
+impl Perk {
+	pub const ALL: [Perk; 4] = [Perk::Toughness, Perk::Swiftness, Perk::Ferocity, Perk::Endurance];
+	
+	/// How much the perk adds to the stat it improves.
+	pub fn amount(&self) -> f32 {
+		match self {
+			Perk::Toughness => 25.0,
+			Perk::Swiftness => 0.25,
+			Perk::Ferocity => 10.0,
+			Perk::Endurance => 50.0
+		}
+	}
+	
+	/// What the perk does, for the perk selection screen.
+	pub fn describe(&self) -> String {
+		match self {
+			Perk::Toughness => format!("Toughness: +{} maximum health", self.amount()),
+			Perk::Swiftness => format!("Swiftness: +{} speed", self.amount()),
+			Perk::Ferocity => format!("Ferocity: +{} damage", self.amount()),
+			Perk::Endurance => format!("Endurance: +{} maximum stamina", self.amount())
+		}
+	}
+}
+
+/// How much experience the player has, what level that makes them, and the perks they've picked so far.
+/// Every level gained earns a perk, which doesn't have to be picked straight away.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Progression {
+	pub level: u32,
+	pub experience: u32,		// Experience earned towards the next level, not in total
+	pub perks: Vec<Perk>,
+	pub unspent_perks: u32
+}
+
+impl Default for Progression {
+	fn default() -> Self {
+		Self { level: 1, experience: 0, perks: Vec::new(), unspent_perks: 0 }
+	}
+}
+
+impl Progression {
+	/// How much experience it takes to get from the current level to the next one.
+	pub fn experience_needed(&self) -> u32 {
+		self.level * 100
+	}
+	
+	/// Earn some experience. Returns how many levels were gained, which can be more than one for a big reward.
+	pub fn gain(&mut self, amount: u32) -> u32 {
+		self.experience += amount;
+		
+		let mut levels = 0;
+		while self.experience >= self.experience_needed() {
+			self.experience -= self.experience_needed();
+			self.level += 1;
+			self.unspent_perks += 1;
+			levels += 1;
+		}
+		levels
+	}
+	
+	/// Use up one of the perks earned from levelling up. Returns false if there aren't any left to pick.
+	pub fn pick(&mut self, perk: Perk) -> bool {
+		if self.unspent_perks == 0 { return false; }
+		self.unspent_perks -= 1;
+		self.perks.push(perk);
+		true
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	#[test]
+	fn gain_one_level() {
+		let mut progression = Progression::default();
+		assert_eq!(progression.gain(60), 0);
+		assert_eq!(progression.gain(50), 1);
+		assert_eq!(progression.level, 2);
+		assert_eq!(progression.experience, 10);
+		assert_eq!(progression.unspent_perks, 1);
+	}
+	
+	#[test]
+	fn gain_several_levels() {
+		// 100 to reach level 2, 200 more for level 3 and 300 more for level 4.
+		let mut progression = Progression::default();
+		assert_eq!(progression.gain(650), 3);
+		assert_eq!(progression.level, 4);
+		assert_eq!(progression.experience, 50);
+		assert_eq!(progression.unspent_perks, 3);
+		assert_eq!(progression.experience_needed(), 400);
+	}
+	
+	#[test]
+	fn pick() {
+		let mut progression = Progression::default();
+		assert!(!progression.pick(Perk::Toughness));
+		assert!(progression.perks.is_empty());
+		
+		progression.gain(100);
+		assert!(progression.pick(Perk::Ferocity));
+		assert!(!progression.pick(Perk::Swiftness));
+		assert_eq!(progression.perks, vec![Perk::Ferocity]);
+		assert_eq!(progression.unspent_perks, 0);
+	}
+	
+	#[test]
+	fn descriptions_match_amounts() {
+		assert_eq!(Perk::Toughness.describe(), "Toughness: +25 maximum health");
+		assert_eq!(Perk::Swiftness.describe(), "Swiftness: +0.25 speed");
+		for perk in Perk::ALL {
+			assert!(perk.describe().contains(&format!("+{} ", perk.amount())), "{:?}", perk);
+		}
+	}
+}
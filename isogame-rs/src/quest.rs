@@ -0,0 +1,323 @@
+use std::collections::HashMap;
+
+use crate::error;
+use crate::error::LineError;
+use crate::state::GameState;
+
+/// Something that happened in the game which quests might be waiting for.
+pub enum QuestEvent {
+	Killed(String),		// Name of the kind of creature
+	Talked(String),		// Node name of the character
+	Reached(String),	// Path to the level
+	Died
+}
+
+impl QuestEvent {
+	/// The name of the game state counter that tracks how many times this event has happened.
+	pub fn counter(&self) -> String {
+		match self {
+			QuestEvent::Killed(kind) => format!("killed:{}", kind),
+			QuestEvent::Talked(name) => format!("talked:{}", name),
+			QuestEvent::Reached(level) => format!("reached:{}", level),
+			QuestEvent::Died => "died".to_string()
+		}
+	}
+}
+
+/// A single goal that must be met to complete a quest.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Objective {
+	Kill(String, i64),
+	Talk(String),
+	Reach(String),
+	Flag(String)
+}
+
+impl Objective {
+	/// The game state counter this objective is waiting on, if any, and how far it must advance.
+	fn counter(&self) -> Option<(String, i64)> {
+		match self {
+			Objective::Kill(kind, count) => Some((QuestEvent::Killed(kind.clone()).counter(), *count)),
+			Objective::Talk(name) => Some((QuestEvent::Talked(name.clone()).counter(), 1)),
+			Objective::Reach(level) => Some((QuestEvent::Reached(level.clone()).counter(), 1)),
+			Objective::Flag(_) => None
+		}
+	}
+	
+	fn describe(&self) -> String {
+		match self {
+			Objective::Kill(kind, count) => format!("Kill {} {}", count, kind),
+			Objective::Talk(name) => format!("Talk to {}", name),
+			Objective::Reach(level) => format!("Reach {}", level_name(level)),
+			Objective::Flag(flag) => flag.replace('_', " ")
+		}
+	}
+}
+
+/// Turn "res://corridor_scene.tscn" into "corridor".
+fn level_name(path: &str) -> &str {
+	let name = path.rsplit('/').next().unwrap_or(path);
+	let name = name.strip_suffix(".tscn").unwrap_or(name);
+	name.strip_suffix("_scene").unwrap_or(name)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Quest {
+	pub id: String,
+	pub title: String,
+	pub objectives: Vec<Objective>,
+	pub experience: u32		// Rewarded when the quest is completed
+}
+
+impl Quest {
+	/// A quest with no objectives yet, titled with its id until it's given a title.
+	fn new(id: &str) -> Self {
+		Self { id: id.to_string(), title: id.to_string(), objectives: Vec::new(), experience: 0 }
+	}
+}
+
+/// Every quest in the game, parsed from a plain text file:
+///
+/// ```text
+/// [wolf_hunt]
+/// title: Thin the Pack
+/// experience: 150
+/// kill Wolf 3
+/// talk Innkeeper
+/// reach res://outdoor_scene.tscn
+/// flag paid_for_room
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct QuestBook {
+	quests: Vec<Quest>
+}
+
+impl QuestBook {
+	pub fn parse(source: &str) -> Result<Self, LineError> {
+		let quests = error::parse_sections(source, "quest", "quest id", |id, _| Ok(Quest::new(id)), |quest, line| {
+			let error = |message: &str| line.error(message);
+			
+			if let Some(title) = line.text.strip_prefix("title:") {
+				quest.title = title.trim().to_string();
+				return Ok(());
+			}
+			
+			if let Some(experience) = line.text.strip_prefix("experience:") {
+				quest.experience = experience.trim().parse().map_err(|_| error("invalid experience"))?;
+				return Ok(());
+			}
+			
+			let words : Vec<&str> = line.text.split_whitespace().collect();
+			let objective = match words.as_slice() {
+				["kill", kind] => Objective::Kill(kind.to_string(), 1),
+				["kill", kind, count] => Objective::Kill(kind.to_string(), count.parse().map_err(|_| error("invalid count"))?),
+				["talk", name] => Objective::Talk(name.to_string()),
+				["reach", level] => Objective::Reach(level.to_string()),
+				["flag", flag] => Objective::Flag(flag.to_string()),
+				_ => return Err(error("invalid objective"))
+			};
+			quest.objectives.push(objective);
+			Ok(())
+		})?;
+		
+		Ok(Self { quests })
+	}
+	
+	pub fn get(&self, id: &str) -> Option<&Quest> {
+		self.quests.iter().find(|quest| quest.id == id)
+	}
+}
+
+/// Tracks which quests the player has started and finished.
+/// Counting objectives only count events that happened after the quest was started.
+#[derive(Clone, Debug, Default)]
+pub struct QuestLog {
+	active: Vec<(String, HashMap<String, i64>)>,	// Quest id and the counter values when it was started
+	completed: Vec<String>
+}
+
+impl QuestLog {
+	/// Start a quest, if it hasn't been started before.
+	pub fn start(&mut self, quest: &Quest, state: &GameState) {
+		if self.is_started(&quest.id) { return; }
+		
+		let baselines = quest.objectives.iter()
+			.filter_map(|objective| objective.counter())
+			.map(|(counter, _)| (counter.clone(), state.get_counter(&counter)))
+			.collect();
+		self.active.push((quest.id.clone(), baselines));
+	}
+	
+	pub fn is_started(&self, id: &str) -> bool {
+		self.is_completed(id) || self.active.iter().any(|(active, _)| active == id)
+	}
+	
+	pub fn is_completed(&self, id: &str) -> bool {
+		self.completed.iter().any(|completed| completed == id)
+	}
+	
+	/// Complete any active quests whose objectives have all been met. Returns the newly completed quests.
+	pub fn update<'a>(&mut self, book: &'a QuestBook, state: &GameState) -> Vec<&'a Quest> {
+		let mut finished = Vec::new();
+		
+		self.active.retain(|(id, baselines)| {
+			let quest = match book.get(id) {
+				Some(x) => x,
+				None => return true
+			};
+			
+			if quest.objectives.iter().all(|objective| progress(objective, baselines, state).0) {
+				finished.push(quest);
+				false
+			} else {
+				true
+			}
+		});
+		
+		self.completed.extend(finished.iter().map(|quest| quest.id.clone()));
+		finished
+	}
+	
+	/// A human-readable summary of every quest, for the quest log.
+	pub fn describe(&self, book: &QuestBook, state: &GameState) -> String {
+		let mut text = String::new();
+		
+		for (id, baselines) in self.active.iter() {
+			let quest = match book.get(id) {
+				Some(x) => x,
+				None => continue
+			};
+			
+			text.push_str(&format!("{}\n", quest.title));
+			for objective in quest.objectives.iter() {
+				let (done, status) = progress(objective, baselines, state);
+				let mark = if done { "x" } else { " " };
+				text.push_str(&format!("  [{}] {}{}\n", mark, objective.describe(), status));
+			}
+		}
+		
+		for id in self.completed.iter() {
+			if let Some(quest) = book.get(id) {
+				text.push_str(&format!("{} (complete)\n", quest.title));
+			}
+		}
+		
+		if text.is_empty() { text.push_str("No quests."); }
+		text
+	}
+	
+	pub fn active(&self) -> &[(String, HashMap<String, i64>)] { &self.active }
+	
+	pub fn completed(&self) -> &[String] { &self.completed }
+	
+	/// Rebuild a quest log from previously saved data.
+	pub fn restore(active: Vec<(String, HashMap<String, i64>)>, completed: Vec<String>) -> Self {
+		Self { active, completed }
+	}
+}
+
+/// Whether an objective is met, and a short description of its progress.
+fn progress(objective: &Objective, baselines: &HashMap<String, i64>, state: &GameState) -> (bool, String) {
+	match objective.counter() {
+		Some((counter, required)) => {
+			let baseline = baselines.get(&counter).copied().unwrap_or(0);
+			let count = (state.get_counter(&counter) - baseline).min(required);
+			let status = if required > 1 { format!(" ({}/{})", count, required) } else { String::new() };
+			(count >= required, status)
+		},
+		None => match objective {
+			Objective::Flag(flag) => (state.has_flag(flag), String::new()),
+			_ => (false, String::new())
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	const SOURCE: &str = "
+		# Comments and blank lines are ignored.
+		[wolf_hunt]
+		title: Thin the Pack
+		experience: 150
+		kill Wolf 3
+		reach res://outdoor_scene.tscn
+		
+		[room]
+		flag paid_for_room
+	";
+	
+	#[test]
+	fn parse() {
+		let book = QuestBook::parse(SOURCE).unwrap();
+		let quest = book.get("wolf_hunt").unwrap();
+		assert_eq!(quest.title, "Thin the Pack");
+		assert_eq!(quest.experience, 150);
+		assert_eq!(quest.objectives, vec![
+			Objective::Kill("Wolf".to_string(), 3),
+			Objective::Reach("res://outdoor_scene.tscn".to_string())
+		]);
+		
+		// The title defaults to the id.
+		let room = book.get("room").unwrap();
+		assert_eq!(room.title, "room");
+		assert_eq!(room.experience, 0);
+		assert!(book.get("missing").is_none());
+	}
+	
+	#[test]
+	fn parse_errors() {
+		let error = |source: &str| QuestBook::parse(source).unwrap_err();
+		assert_eq!(error("kill Wolf"), LineError::new(1, "content before the first quest"));
+		assert_eq!(error("[a]\n[a]").message, "duplicate quest id");
+		assert_eq!(error("[a]\nkill Wolf many").message, "invalid count");
+		assert_eq!(error("[a]\nexperience: lots").message, "invalid experience");
+		assert_eq!(error("[a]\nfly away"), LineError::new(2, "invalid objective"));
+	}
+	
+	#[test]
+	fn counters_start_from_quest_start() {
+		let book = QuestBook::parse(SOURCE).unwrap();
+		let mut state = GameState::default();
+		let mut log = QuestLog::default();
+		
+		// Wolves killed before the quest was started don't count towards it.
+		let killed = QuestEvent::Killed("Wolf".to_string()).counter();
+		state.set_counter(&killed, 5);
+		log.start(book.get("wolf_hunt").unwrap(), &state);
+		state.increment(&QuestEvent::Reached("res://outdoor_scene.tscn".to_string()).counter());
+		assert!(log.update(&book, &state).is_empty());
+		
+		state.increment(&killed);
+		state.increment(&killed);
+		assert!(log.update(&book, &state).is_empty());
+		assert!(log.describe(&book, &state).contains("Kill 3 Wolf (2/3)"));
+		
+		state.increment(&killed);
+		let finished : Vec<&str> = log.update(&book, &state).iter().map(|quest| quest.id.as_str()).collect();
+		assert_eq!(finished, ["wolf_hunt"]);
+	}
+	
+	#[test]
+	fn completion() {
+		let book = QuestBook::parse(SOURCE).unwrap();
+		let mut state = GameState::default();
+		let mut log = QuestLog::default();
+		
+		log.start(book.get("room").unwrap(), &state);
+		assert!(log.is_started("room"));
+		assert!(!log.is_completed("room"));
+		
+		state.set_flag("paid_for_room");
+		assert_eq!(log.update(&book, &state).len(), 1);
+		assert!(log.is_completed("room"));
+		assert!(log.active().is_empty());
+		
+		// Completed quests are only reported once, and can't be started again.
+		assert!(log.update(&book, &state).is_empty());
+		log.start(book.get("room").unwrap(), &state);
+		assert!(log.active().is_empty());
+		assert_eq!(log.completed(), ["room"]);
+	}
+}
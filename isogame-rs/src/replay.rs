@@ -0,0 +1,191 @@
+use crate::error;
+use crate::error::LineError;
+use crate::util::IsometricFacing;
+use crate::util::KeyboardInput;
+
+/// Everything the player did during one physics frame.
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct InputFrame {
+	pub movement: Option<IsometricFacing>,
+	pub interact: bool,
+	pub sprint: bool,
+	pub quest_log: bool,
+	pub choice: Option<i64>		// Index of the dialogue choice picked, or -1 to close a conversation with no choices
+}
+
+impl InputFrame {
+	/// Read this frame's input from the keyboard. Dialogue choices come from the dialogue box, so are filled in separately.
+	pub fn poll() -> Self {
+		Self {
+			movement: KeyboardInput::get_movement(),
+			interact: KeyboardInput::get_interact(),
+			sprint: KeyboardInput::get_sprint(),
+			quest_log: KeyboardInput::get_quest_log(),
+			choice: None
+		}
+	}
+}
+
+/// A recorded play session: the level and random seed it started with, and the player's input on every physics frame.
+///
+/// Stored as plain text, with runs of identical frames written once along with how many times they repeat:
+///
+/// ```text
+/// seed 1234
+/// level res://level.tscn
+/// 90
+/// 12 nw
+/// 30 se sprint
+/// 1 interact
+/// 1 choice=0
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InputRecording {
+	pub seed: u64,
+	pub level: String,
+	frames: Vec<InputFrame>
+}
+
+impl InputRecording {
+	pub fn new(seed: u64, level: &str) -> Self {
+		Self { seed, level: level.to_string(), frames: Vec::new() }
+	}
+	
+	pub fn push(&mut self, frame: InputFrame) { self.frames.push(frame); }
+	
+	pub fn get(&self, index: usize) -> Option<&InputFrame> { self.frames.get(index) }
+	
+	pub fn parse(source: &str) -> Result<Self, LineError> {
+		let mut recording = Self::default();
+		let mut seed = None;
+		
+		for source_line in error::source_lines(source) {
+			let line = source_line.text;
+			let error = |message: &str| source_line.error(message);
+			
+			if let Some(value) = line.strip_prefix("seed ") {
+				seed = Some(value.trim().parse().map_err(|_| error("invalid seed"))?);
+				continue;
+			}
+			if let Some(path) = line.strip_prefix("level ") {
+				recording.level = path.trim().to_string();
+				continue;
+			}
+			
+			let (count, frame) = parse_frames(line).map_err(|message| error(&message))?;
+			recording.frames.extend(std::iter::repeat_n(frame, count));
+		}
+		
+		recording.seed = seed.ok_or(LineError::new(1, "missing seed"))?;
+		Ok(recording)
+	}
+	
+	pub fn to_text(&self) -> String {
+		let mut text = format!("seed {}\nlevel {}\n", self.seed, self.level);
+		
+		let mut index = 0;
+		while index < self.frames.len() {
+			let frame = &self.frames[index];
+			let count = self.frames[index..].iter().take_while(|other| *other == frame).count();
+			
+			let mut line = count.to_string();
+			if let Some(facing) = &frame.movement { line += &format!(" {}", facing); }
+			if frame.interact { line += " interact"; }
+			if frame.sprint { line += " sprint"; }
+			if frame.quest_log { line += " quest_log"; }
+			if let Some(choice) = frame.choice { line += &format!(" choice={}", choice); }
+			
+			text += &line;
+			text += "\n";
+			index += count;
+		}
+		
+		text
+	}
+}
+
+/// Parse a run of identical frames, like `12 nw interact`, into how many frames there are and what was pressed during them.
+pub fn parse_frames(line: &str) -> Result<(usize, InputFrame), String> {
+	let mut words = line.split_whitespace();
+	let count = words.next().unwrap_or_default().parse().map_err(|_| "invalid frame count".to_string())?;
+	let mut frame = InputFrame::default();
+	
+	for word in words {
+		match word {
+			"nw" => frame.movement = Some(IsometricFacing::NW),
+			"ne" => frame.movement = Some(IsometricFacing::NE),
+			"sw" => frame.movement = Some(IsometricFacing::SW),
+			"se" => frame.movement = Some(IsometricFacing::SE),
+			"interact" => frame.interact = true,
+			"sprint" => frame.sprint = true,
+			"quest_log" => frame.quest_log = true,
+			_ => match word.strip_prefix("choice=") {
+				Some(index) => frame.choice = Some(index.parse().map_err(|_| "invalid choice".to_string())?),
+				None => return Err(format!("unknown input '{}'", word))
+			}
+		}
+	}
+	
+	Ok((count, frame))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	const SOURCE: &str = "
+		# Comments are ignored.
+		seed 1234
+		level res://level.tscn
+		90
+		12 nw
+		30 se sprint
+		1 interact
+		1 choice=0
+		1 choice=-1
+	";
+	
+	#[test]
+	fn round_trip() {
+		let recording = InputRecording::parse(SOURCE).unwrap();
+		assert_eq!(recording.seed, 1234);
+		assert_eq!(recording.level, "res://level.tscn");
+		assert_eq!(recording.frames.len(), 135);
+		assert_eq!(InputRecording::parse(&recording.to_text()).unwrap(), recording);
+	}
+	
+	#[test]
+	fn merges_runs() {
+		let mut recording = InputRecording::new(7, "res://level.tscn");
+		let walk = InputFrame { movement: Some(IsometricFacing::NE), ..InputFrame::default() };
+		for _ in 0..3 { recording.push(InputFrame::default()); }
+		for _ in 0..5 { recording.push(walk.clone()); }
+		recording.push(InputFrame::default());
+		
+		assert_eq!(recording.to_text(), "seed 7\nlevel res://level.tscn\n3\n5 ne\n1\n");
+	}
+	
+	#[test]
+	fn choices() {
+		let (count, frame) = parse_frames("2 choice=3").unwrap();
+		assert_eq!(count, 2);
+		assert_eq!(frame.choice, Some(3));
+		assert_eq!(parse_frames("1 choice=-1").unwrap().1.choice, Some(-1));
+		assert!(parse_frames("1 choice=first").is_err());
+		
+		let recording = InputRecording::parse(SOURCE).unwrap();
+		assert_eq!(recording.get(133).unwrap().choice, Some(0));
+		assert_eq!(recording.get(134).unwrap().choice, Some(-1));
+		assert_eq!(recording.get(132).unwrap().choice, None);
+	}
+	
+	#[test]
+	fn errors() {
+		assert_eq!(parse_frames("1 jump").unwrap_err(), "unknown input 'jump'");
+		assert!(parse_frames("many nw").is_err());
+		
+		let error = InputRecording::parse("seed 1\n2 nw\n1 fly").unwrap_err();
+		assert_eq!(error.line, 3);
+		assert_eq!(InputRecording::parse("2 nw").unwrap_err().message, "missing seed");
+	}
+}
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt;
+
+use godot::prelude::*;
+use godot::classes::AStarGrid2D;
+
+/// Why a tile couldn't be reserved.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReservationConflict {
+	Static,					// A wall, static scenery or a closed door is there
+	OutOfBounds,			// The tile is off the edge of the navigation grid
+	Owned(InstanceId)		// Somebody else has already reserved it
+}
+
+impl fmt::Display for ReservationConflict {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ReservationConflict::Static => write!(f, "something that never moves is in the way"),
+			ReservationConflict::OutOfBounds => write!(f, "it is off the edge of the level"),
+			ReservationConflict::Owned(owner) => write!(f, "it is already reserved by {}", owner)
+		}
+	}
+}
+
+impl std::error::Error for ReservationConflict {}
+
+/// Keeps track of which tiles are blocked, and why: either by something static like a wall, or because a moving entity is
+/// standing on it or about to step onto it. Each reservation has an owner, and only the owner can release it, so a
+/// creature can never free up a wall or somebody else's tile.
+#[derive(Default)]
+pub struct TileReservations {
+	statics: HashSet<Vector2i>,
+	owners: HashMap<Vector2i, InstanceId>
+}
+
+impl TileReservations {
+	/// Reserve a tile for an entity. Reserving a tile it already holds succeeds without doing anything.
+	pub fn reserve(&mut self, tile: Vector2i, owner: InstanceId) -> Result<(), ReservationConflict> {
+		if self.statics.contains(&tile) { return Err(ReservationConflict::Static); }
+		
+		match self.owners.get(&tile) {
+			Some(current) if *current != owner => Err(ReservationConflict::Owned(*current)),
+			_ => {
+				self.owners.insert(tile, owner);
+				Ok(())
+			}
+		}
+	}
+	
+	/// Release a tile, if it's reserved by this owner. Returns whether anything was released.
+	pub fn release(&mut self, tile: Vector2i, owner: InstanceId) -> bool {
+		if self.owners.get(&tile) != Some(&owner) { return false; }
+		self.owners.remove(&tile);
+		true
+	}
+	
+	/// Mark a tile as blocked, or not, by something static. This doesn't affect any reservation on it.
+	pub fn set_static(&mut self, tile: Vector2i, blocked: bool) {
+		if blocked { self.statics.insert(tile); } else { self.statics.remove(&tile); }
+	}
+	
+	/// Forget every static tile, returning them, so they can be worked out again from scratch.
+	pub fn clear_statics(&mut self) -> HashSet<Vector2i> {
+		std::mem::take(&mut self.statics)
+	}
+	
+	/// Every tile blocked by something static.
+	pub fn statics(&self) -> impl Iterator<Item = Vector2i> + '_ {
+		self.statics.iter().copied()
+	}
+	
+	/// Who has reserved a tile, if anybody.
+	pub fn owner(&self, tile: Vector2i) -> Option<InstanceId> {
+		self.owners.get(&tile).copied()
+	}
+	
+	/// Whether anything, static or not, is blocking a tile.
+	pub fn is_blocked(&self, tile: Vector2i) -> bool {
+		self.statics.contains(&tile) || self.owners.contains_key(&tile)
+	}
+}
+
+/// A level's reservations, shared with everything that moves around it so that each can reserve its next tile the moment
+/// it decides to step there. Every change is copied onto the navigation grid straight away, so pathfinding always agrees.
+#[derive(GodotClass)]
+#[class(base=RefCounted,init)]
+pub struct ReservationGrid {
+	pub tiles: TileReservations,
+	nav: Option<Gd<AStarGrid2D>>,
+	base: Base<RefCounted>
+}
+
+impl ReservationGrid {
+	pub fn set_nav(&mut self, nav: Gd<AStarGrid2D>) { self.nav = Some(nav); }
+	
+	/// Reserve a tile for an entity, and block it on the navigation grid. Tiles off the edge of the grid can't be reserved.
+	pub fn reserve(&mut self, tile: Vector2i, owner: InstanceId) -> Result<(), ReservationConflict> {
+		if self.nav.as_ref().is_some_and(|nav| !nav.is_in_boundsv(tile)) { return Err(ReservationConflict::OutOfBounds); }
+		self.tiles.reserve(tile, owner)?;
+		self.update_tile(tile);
+		Ok(())
+	}
+	
+	/// Release a tile, if it's reserved by this owner, and unblock it on the navigation grid unless something static is there.
+	pub fn release(&mut self, tile: Vector2i, owner: InstanceId) {
+		if self.tiles.release(tile, owner) { self.update_tile(tile); }
+	}
+	
+	/// Bring the navigation grid up to date with whatever is blocking a tile.
+	pub fn update_tile(&mut self, tile: Vector2i) {
+		let blocked = self.tiles.is_blocked(tile);
+		if let Some(nav) = self.nav.as_mut() {
+			nav.set_point_solid_ex(tile).solid(blocked).done();
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	const TILE: Vector2i = Vector2i::new(3, 4);
+	
+	fn ids() -> (InstanceId, InstanceId) {
+		(InstanceId::from_i64(1), InstanceId::from_i64(2))
+	}
+	
+	#[test]
+	fn only_the_owner_can_release() {
+		let (owner, other) = ids();
+		let mut reservations = TileReservations::default();
+		reservations.reserve(TILE, owner).unwrap();
+		
+		assert_eq!(reservations.reserve(TILE, other), Err(ReservationConflict::Owned(owner)));
+		assert!(!reservations.release(TILE, other));
+		assert_eq!(reservations.owner(TILE), Some(owner));
+		
+		assert!(reservations.release(TILE, owner));
+		assert!(!reservations.is_blocked(TILE));
+	}
+	
+	#[test]
+	fn statics_cant_be_reserved() {
+		let (owner, _) = ids();
+		let mut reservations = TileReservations::default();
+		reservations.set_static(TILE, true);
+		
+		assert_eq!(reservations.reserve(TILE, owner), Err(ReservationConflict::Static));
+		assert_eq!(reservations.owner(TILE), None);
+		assert!(!reservations.release(TILE, owner));
+		assert!(reservations.is_blocked(TILE));
+	}
+	
+	#[test]
+	fn reserving_again_is_idempotent() {
+		let (owner, _) = ids();
+		let mut reservations = TileReservations::default();
+		reservations.reserve(TILE, owner).unwrap();
+		reservations.reserve(TILE, owner).unwrap();
+		
+		// A single release frees it, since there's only ever one reservation per tile.
+		assert!(reservations.release(TILE, owner));
+		assert!(!reservations.is_blocked(TILE));
+	}
+	
+	#[test]
+	fn clearing_statics() {
+		let (owner, _) = ids();
+		let mut reservations = TileReservations::default();
+		reservations.set_static(TILE, true);
+		reservations.reserve(Vector2i::ZERO, owner).unwrap();
+		
+		let previous = reservations.clear_statics();
+		assert!(previous.contains(&TILE));
+		assert!(!reservations.is_blocked(TILE));
+		assert_eq!(reservations.owner(Vector2i::ZERO), Some(owner));
+		
+		reservations.set_static(TILE, true);
+		assert!(reservations.is_blocked(TILE));
+		assert_eq!(reservations.reserve(TILE, owner), Err(ReservationConflict::Static));
+	}
+}
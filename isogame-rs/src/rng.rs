@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use godot::prelude::*;
+
+/// A small, fast pseudo-random number generator (SplitMix64). The same seed always produces the same sequence,
+/// on every platform, so anything built from it can be reproduced exactly.
+#[derive(Clone, Debug)]
+pub struct Rng {
+	state: u64
+}
+
+impl Rng {
+	pub fn new(seed: u64) -> Self {
+		Self { state: seed }
+	}
+	
+	/// The generator's current position in its sequence. `Rng::new(rng.state())` carries on exactly where `rng` left off.
+	pub fn state(&self) -> u64 {
+		self.state
+	}
+	
+	pub fn next_u64(&mut self) -> u64 {
+		self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+		let mut z = self.state;
+		z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+		z ^ (z >> 31)
+	}
+	
+	/// A random float between 0 (inclusive) and 1 (exclusive).
+	pub fn float(&mut self) -> f64 {
+		(self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+	}
+	
+	/// A random integer between `min` and `max`, inclusive. Returns `min` if the range is empty.
+	pub fn range(&mut self, min: i32, max: i32) -> i32 {
+		if max <= min { return min; }
+		let span = (max as i64 - min as i64 + 1) as u64;
+		(min as i64 + (self.next_u64() % span) as i64) as i32
+	}
+	
+	/// A random float between `min` (inclusive) and `max` (exclusive).
+	pub fn range_f64(&mut self, min: f64, max: f64) -> f64 {
+		min + self.float() * (max - min)
+	}
+	
+	/// Returns true with the given probability, from 0 to 1.
+	pub fn chance(&mut self, probability: f64) -> bool {
+		self.float() < probability
+	}
+	
+	/// Pick a random item from a slice.
+	pub fn pick<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+		if items.is_empty() { return None; }
+		let index = self.range(0, items.len() as i32 - 1) as usize;
+		items.get(index)
+	}
+	
+	/// Shuffle a slice in place.
+	pub fn shuffle<T>(&mut self, items: &mut [T]) {
+		for i in (1..items.len()).rev() {
+			let j = self.range(0, i as i32) as usize;
+			items.swap(i, j);
+		}
+	}
+}
+
+/// Every random number used by the game comes from here, so that a whole run can be reproduced from one seed.
+///
+/// Each system draws from its own named substream, derived from the seed and the name. That way, one system using
+/// more or fewer random numbers doesn't change what any of the others get.
+#[derive(Clone, Default, Debug)]
+pub struct RandomStreams {
+	seed: u64,
+	streams: HashMap<String, Rng>
+}
+
+impl RandomStreams {
+	pub fn new(seed: u64) -> Self {
+		Self { seed, streams: HashMap::new() }
+	}
+	
+	pub fn seed(&self) -> u64 { self.seed }
+	
+	/// The substream for a system, which starts from the beginning the first time it's asked for.
+	pub fn stream(&mut self, name: &str) -> &mut Rng {
+		let seed = self.seed;
+		self.streams.entry(name.to_string()).or_insert_with(|| Rng::new(seed ^ hash_name(name)))
+	}
+	
+	/// The current state of every substream that has been used, for saving.
+	pub fn states(&self) -> impl Iterator<Item = (&String, u64)> {
+		self.streams.iter().map(|(name, rng)| (name, rng.state()))
+	}
+	
+	/// Carry on a saved run, with each substream picking up where it left off.
+	pub fn restore(seed: u64, states: Vec<(String, u64)>) -> Self {
+		let streams = states.into_iter().map(|(name, state)| (name, Rng::new(state))).collect();
+		Self { seed, streams }
+	}
+}
+
+/// The random number service shared between the level manager and everything in the current level.
+#[derive(GodotClass)]
+#[class(base=RefCounted,init)]
+pub struct GameRng {
+	pub streams: RandomStreams,
+	base: Base<RefCounted>
+}
+
+impl GameRng {
+	pub fn stream(&mut self, name: &str) -> &mut Rng {
+		self.streams.stream(name)
+	}
+}
+
+/// Names of the substreams used by each system.
+pub mod stream {
+	pub const WANDER: &str = "wander";
+	pub const CAMERA: &str = "camera";
+	pub const LOOT: &str = "loot";
+	pub const GENERATION: &str = "generation";
+}
+
+/// FNV-1a, run through one round of SplitMix64 so that similar names give very different seeds.
+fn hash_name(name: &str) -> u64 {
+	let mut hash: u64 = 0xCBF2_9CE4_8422_2325;
+	for byte in name.bytes() {
+		hash ^= byte as u64;
+		hash = hash.wrapping_mul(0x0000_0100_0000_01B3);
+	}
+	Rng::new(hash).next_u64()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn take(rng: &mut Rng, count: usize) -> Vec<u64> {
+		(0..count).map(|_| rng.next_u64()).collect()
+	}
+	
+	#[test]
+	fn substreams_are_independent() {
+		let mut quiet = RandomStreams::new(7);
+		let mut busy = RandomStreams::new(7);
+		take(busy.stream(stream::CAMERA), 100);
+		
+		assert_eq!(take(quiet.stream(stream::LOOT), 10), take(busy.stream(stream::LOOT), 10));
+		assert_ne!(take(quiet.stream(stream::LOOT), 10), take(quiet.stream(stream::WANDER), 10));
+	}
+	
+	#[test]
+	fn restore_resumes() {
+		let mut streams = RandomStreams::new(99);
+		take(streams.stream(stream::WANDER), 5);
+		take(streams.stream(stream::LOOT), 3);
+		
+		let states = streams.states().map(|(name, state)| (name.clone(), state)).collect();
+		let mut restored = RandomStreams::restore(streams.seed(), states);
+		assert_eq!(restored.seed(), 99);
+		assert_eq!(take(restored.stream(stream::WANDER), 10), take(streams.stream(stream::WANDER), 10));
+		assert_eq!(take(restored.stream(stream::LOOT), 10), take(streams.stream(stream::LOOT), 10));
+		
+		// Streams that weren't used before saving start from the beginning, as they would have.
+		assert_eq!(take(restored.stream(stream::CAMERA), 10), take(streams.stream(stream::CAMERA), 10));
+	}
+}
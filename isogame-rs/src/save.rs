@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+
+use godot::prelude::*;
+use godot::classes::ConfigFile;
+use godot::global::Error;
+
+use crate::inventory::Inventory;
+use crate::level::LevelState;
+use crate::player::PlayerData;
+use crate::progression::Perk;
+use crate::progression::Progression;
+use crate::quest::QuestLog;
+use crate::rng::RandomStreams;
+use crate::state::GameState;
+use crate::util::IsometricFacing;
+
+/// Everything needed to resume a game, stored on disk as a Godot `ConfigFile`.
+pub struct SaveGame {
+	pub level: GString,
+	pub coords: Vector2,
+	pub facing: IsometricFacing,
+	pub levels: HashMap<String, LevelState>,	// Path to the level and its state, for every level that has been visited
+	pub player: PlayerData,
+	pub state: GameState,
+	pub quests: QuestLog,
+	pub random: RandomStreams
+}
+
+impl SaveGame {
+	pub fn write(&self, path: &GString) -> Result<(), Error> {
+		let mut file = ConfigFile::new_gd();
+		
+		file.set_value("world", "level", &self.level.to_variant());
+		file.set_value("world", "coords", &self.coords.to_variant());
+		file.set_value("world", "facing", &self.facing.to_variant());
+		let levels : Dictionary = self.levels.iter().map(|(path, state)| {
+			let interactables : Dictionary = state.interactables().iter().map(|(node, active)| (node.to_variant(), active.to_variant())).collect();
+			(path.to_variant(), interactables.to_variant())
+		}).collect();
+		file.set_value("world", "levels", &levels.to_variant());
+		
+		file.set_value("player", "speed", &self.player.speed.to_variant());
+		file.set_value("player", "health", &self.player.health.to_variant());
+		file.set_value("player", "max_health", &self.player.max_health.to_variant());
+		file.set_value("player", "stamina", &self.player.stamina.to_variant());
+		file.set_value("player", "max_stamina", &self.player.max_stamina.to_variant());
+		file.set_value("player", "damage", &self.player.damage.to_variant());
+		let inventory : Dictionary = self.player.inventory.items().map(|(item, quantity)| (item.to_variant(), quantity.to_variant())).collect();
+		file.set_value("player", "inventory", &inventory.to_variant());
+		
+		let progression = &self.player.progression;
+		file.set_value("progression", "level", &progression.level.to_variant());
+		file.set_value("progression", "experience", &progression.experience.to_variant());
+		let perks : PackedStringArray = progression.perks.iter().map(|perk| perk.to_godot()).collect();
+		file.set_value("progression", "perks", &perks.to_variant());
+		file.set_value("progression", "unspent_perks", &progression.unspent_perks.to_variant());
+		
+		let flags : PackedStringArray = self.state.flags().map(GString::from).collect();
+		file.set_value("state", "flags", &flags.to_variant());
+		let counters : Dictionary = self.state.counters().map(|(counter, value)| (counter.to_variant(), value.to_variant())).collect();
+		file.set_value("state", "counters", &counters.to_variant());
+		
+		let active : Dictionary = self.quests.active().iter().map(|(id, baselines)| {
+			let baselines : Dictionary = baselines.iter().map(|(counter, value)| (counter.to_variant(), value.to_variant())).collect();
+			(id.to_variant(), baselines.to_variant())
+		}).collect();
+		file.set_value("quests", "active", &active.to_variant());
+		let completed : PackedStringArray = self.quests.completed().iter().map(GString::from).collect();
+		file.set_value("quests", "completed", &completed.to_variant());
+		
+		// Godot only has signed integers, so the random state is stored bit-for-bit as an i64.
+		file.set_value("random", "seed", &(self.random.seed() as i64).to_variant());
+		let streams : Dictionary = self.random.states().map(|(name, state)| (name.to_variant(), (state as i64).to_variant())).collect();
+		file.set_value("random", "streams", &streams.to_variant());
+		
+		match file.save(path) {
+			Error::OK => Ok(()),
+			e => Err(e)
+		}
+	}
+	
+	/// Read a save file, if one exists.
+	pub fn read(path: &GString) -> Option<Self> {
+		let mut file = ConfigFile::new_gd();
+		if file.load(path) != Error::OK { return None; }
+		
+		let get = |section: &str, key: &str| file.get_value(section, key);
+		let defaults = PlayerData::default();
+		
+		let mut levels = HashMap::new();
+		for (path, interactables) in get("world", "levels").try_to::<Dictionary>().unwrap_or_default().iter_shared() {
+			let interactables = interactables.try_to::<Dictionary>().unwrap_or_default().iter_shared()
+				.map(|(node, active)| (node.to_string(), active.try_to().unwrap_or(false)))
+				.collect();
+			levels.insert(path.to_string(), LevelState::restore(interactables));
+		}
+		
+		let mut inventory = Inventory::default();
+		for (item, quantity) in get("player", "inventory").try_to::<Dictionary>().unwrap_or_default().iter_shared() {
+			inventory.add(&item.to_string(), quantity.try_to().unwrap_or(0));
+		}
+		
+		let progression = Progression {
+			level: get("progression", "level").try_to().unwrap_or(defaults.progression.level),
+			experience: get("progression", "experience").try_to().unwrap_or(defaults.progression.experience),
+			perks: get("progression", "perks").try_to::<PackedStringArray>().unwrap_or_default().as_slice()
+				.iter()
+				.filter_map(|perk| Perk::try_from_godot(perk.clone()).ok())
+				.collect(),
+			unspent_perks: get("progression", "unspent_perks").try_to().unwrap_or(defaults.progression.unspent_perks)
+		};
+		
+		let player = PlayerData {
+			speed: get("player", "speed").try_to().unwrap_or(defaults.speed),
+			health: get("player", "health").try_to().unwrap_or(defaults.health),
+			max_health: get("player", "max_health").try_to().unwrap_or(defaults.max_health),
+			stamina: get("player", "stamina").try_to().unwrap_or(defaults.stamina),
+			max_stamina: get("player", "max_stamina").try_to().unwrap_or(defaults.max_stamina),
+			damage: get("player", "damage").try_to().unwrap_or(defaults.damage),
+			inventory,
+			progression
+		};
+		
+		let mut state = GameState::default();
+		for flag in get("state", "flags").try_to::<PackedStringArray>().unwrap_or_default().as_slice() {
+			state.set_flag(&flag.to_string());
+		}
+		for (counter, value) in get("state", "counters").try_to::<Dictionary>().unwrap_or_default().iter_shared() {
+			state.set_counter(&counter.to_string(), value.try_to().unwrap_or(0));
+		}
+		
+		let mut active = Vec::new();
+		for (id, baselines) in get("quests", "active").try_to::<Dictionary>().unwrap_or_default().iter_shared() {
+			let baselines : HashMap<String, i64> = baselines.try_to::<Dictionary>().unwrap_or_default().iter_shared()
+				.map(|(counter, value)| (counter.to_string(), value.try_to().unwrap_or(0)))
+				.collect();
+			active.push((id.to_string(), baselines));
+		}
+		let completed = get("quests", "completed").try_to::<PackedStringArray>().unwrap_or_default().as_slice()
+			.iter()
+			.map(|id| id.to_string())
+			.collect();
+		
+		let streams = get("random", "streams").try_to::<Dictionary>().unwrap_or_default().iter_shared()
+			.map(|(name, state)| (name.to_string(), state.try_to::<i64>().unwrap_or(0) as u64))
+			.collect();
+		let random = RandomStreams::restore(get("random", "seed").try_to::<i64>().unwrap_or(0) as u64, streams);
+		
+		Some(Self {
+			level: get("world", "level").try_to().ok()?,
+			coords: get("world", "coords").try_to().ok()?,
+			facing: get("world", "facing").try_to().unwrap_or_default(),
+			levels,
+			player,
+			state,
+			quests: QuestLog::restore(active, completed),
+			random
+		})
+	}
+}
@@ -0,0 +1,129 @@
+use godot::prelude::*;
+use godot::classes::StaticBody2D;
+use godot::classes::IStaticBody2D;
+
+use crate::character::MovementState;
+use crate::character::MovementUpdate;
+use crate::character::MovingCharacter;
+use crate::character::Easing;
+use crate::util::IsometricFacing;
+
+/// A static object on the grid, like a barrel, which blocks movement.
+/// It can optionally be pushed around by the player, or broken to drop loot, which the level spreads out over free tiles.
+#[derive(GodotClass)]
+#[class(base=StaticBody2D,init)]
+pub struct Scenery {
+	/// Whether the player can push this object by walking into it.
+	#[export]
+	pushable: bool,
+	
+	/// Whether the player can break this object.
+	#[export]
+	destructible: bool,
+	
+	/// Whether the object is tall enough to hide a character standing behind it, so it fades out like walls do.
+	#[export]
+	tall: bool,
+	
+	#[export]
+	#[init(val = 50.0)]
+	health: f32,
+	
+	#[export]
+	#[init(val = 2.0)]
+	speed: f32,
+	
+	/// How the object speeds up and slows down as it's pushed from one tile to the next.
+	#[export]
+	#[init(val = Easing::EaseOut)]
+	easing: Easing,
+	
+	/// Item dropped when the object is broken.
+	#[export]
+	loot: GString,
+	
+	#[export]
+	#[init(val = 1)]
+	loot_quantity: u32,
+	
+	pub character: MovingCharacter,
+	base: Base<StaticBody2D>
+}
+
+#[godot_api]
+impl Scenery {
+	#[signal]
+	pub fn update_nav(instance: Gd<Scenery>);
+	/// Emitted when the object is broken, with the tile it was on and the item it drops, which is empty if it drops nothing.
+	#[signal]
+	pub fn destroyed(tile: Vector2i, loot: GString, quantity: u32);
+}
+
+#[godot_api]
+impl IStaticBody2D for Scenery {
+	fn ready(&mut self) {
+		self.base_mut().add_to_group("scenery");
+		if self.tall { self.base_mut().add_to_group("occluders"); }
+		self.character.easing = self.easing;
+	}
+	
+	fn physics_process(&mut self, delta: f64) {
+		if !self.character.has_nav() {
+			// If we don't have pathfinding data, request it and wait.
+			self.ask_for_nav();
+			return;
+		}
+		
+		// Destruction logic.
+		if self.destructible && self.health <= 0.00 {
+			self.destroy();
+			return;
+		}
+		
+		// Movement logic.
+		let position = self.base().get_position();
+		let update = self.character.update_movement(position, self.speed, delta);
+		if let MovementUpdate::Moved(position) | MovementUpdate::Arrived(position) = update {
+			self.base_mut().set_position(position);
+		}
+	}
+}
+
+impl Scenery {
+	/// Start sliding one tile in the given direction, if we are pushable. The move happens on the next physics frame.
+	pub fn push(&mut self, facing: IsometricFacing) {
+		if !self.pushable { return; }
+		
+		if self.character.movement == MovementState::Idle {
+			self.character.facing = facing;
+			self.character.movement = MovementState::StartMoving;
+		}
+	}
+	
+	/// Damage the object, if it is destructible. It is destroyed on the next physics frame.
+	pub fn damage(&mut self, damage: f32) {
+		if !self.destructible { return; }
+		self.health -= damage;
+	}
+	
+	pub fn is_destructible(&self) -> bool { self.destructible }
+	
+	fn destroy(&mut self) {
+		let position = self.base().get_position();
+		let tile = self.character.occupied_tile(position);
+		self.character.leave(position);
+		
+		let loot = self.loot.clone();
+		let quantity = self.loot_quantity;
+		let mut sig = self.signals().destroyed();
+		sig.emit(tile, &loot, quantity);
+		
+		self.base_mut().queue_free();
+	}
+	
+	fn ask_for_nav(&mut self) {
+		let gd = self.to_gd();
+		let mut sig = self.signals().update_nav();
+		sig.emit(&gd);
+	}
+}
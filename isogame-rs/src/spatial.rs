@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+
+use godot::prelude::*;
+
+use crate::faction::Faction;
+
+/// Which tile everything in a level is standing on, so that AI, combat and interactions can find each other by where they
+/// are instead of searching the whole scene tree. The level adds everything to it when it's set up, and removes things as
+/// they leave the tree. Characters move themselves in it each time they step onto a new tile.
+#[derive(GodotClass)]
+#[class(base=RefCounted,init)]
+pub struct EntityIndex {
+	tiles: HashMap<Vector2i, Vec<InstanceId>>,
+	entities: HashMap<InstanceId, Vector2i>,
+	factions: HashMap<InstanceId, Faction>,
+	base: Base<RefCounted>
+}
+
+impl EntityIndex {
+	/// Record which tile an entity is on, and which faction it belongs to.
+	pub fn add(&mut self, entity: InstanceId, tile: Vector2i, faction: Option<Faction>) {
+		match faction {
+			Some(faction) => self.factions.insert(entity, faction),
+			None => self.factions.remove(&entity)
+		};
+		self.move_to(entity, tile);
+	}
+	
+	/// Move an entity onto a new tile. Doing this for an entity that hasn't been added puts it in without a faction.
+	pub fn move_to(&mut self, entity: InstanceId, tile: Vector2i) {
+		match self.entities.insert(entity, tile) {
+			Some(previous) if previous == tile => return,
+			Some(previous) => self.remove_from_tile(entity, previous),
+			None => ()
+		}
+		self.tiles.entry(tile).or_default().push(entity);
+	}
+	
+	/// Forget about an entity, like one that has died or left the level.
+	pub fn remove(&mut self, entity: InstanceId) {
+		self.factions.remove(&entity);
+		if let Some(tile) = self.entities.remove(&entity) {
+			self.remove_from_tile(entity, tile);
+		}
+	}
+	
+	/// Everything of a given class on a tile.
+	pub fn at<T>(&self, tile: Vector2i) -> impl Iterator<Item = Gd<T>> + '_
+	where
+		T: GodotClass + Inherits<Node>
+	{
+		self.tiles.get(&tile).into_iter().flatten().filter_map(|entity| Gd::try_from_instance_id(*entity).ok())
+	}
+	
+	/// Everything of a given class within `radius` steps of a tile, closest first.
+	pub fn within<T>(&self, tile: Vector2i, radius: i32) -> Vec<Gd<T>>
+	where
+		T: GodotClass + Inherits<Node>
+	{
+		let mut found : Vec<(i32, i64, Gd<T>)> = self.entities.iter()
+			.filter(|(_, other)| distance(tile, **other) <= radius)
+			.filter_map(|(entity, other)| Some((distance(tile, *other), entity.to_i64(), Gd::try_from_instance_id(*entity).ok()?)))
+			.collect();
+		
+		// Ties are broken by instance ID, so that the same level always gives the same answer.
+		found.sort_by_key(|(distance, id, _)| (*distance, *id));
+		found.into_iter().map(|(_, _, entity)| entity).collect()
+	}
+	
+	/// The closest thing of a given class within `radius` steps of a tile, belonging to a faction we're looking for.
+	pub fn nearest<T>(&self, tile: Vector2i, radius: i32, wanted: impl Fn(Faction) -> bool) -> Option<Gd<T>>
+	where
+		T: GodotClass + Inherits<Node>
+	{
+		self.within::<T>(tile, radius).into_iter().find(|entity| self.faction(entity.instance_id()).is_some_and(&wanted))
+	}
+	
+	pub fn faction(&self, entity: InstanceId) -> Option<Faction> {
+		self.factions.get(&entity).copied()
+	}
+	
+	fn remove_from_tile(&mut self, entity: InstanceId, tile: Vector2i) {
+		if let Some(entities) = self.tiles.get_mut(&tile) {
+			entities.retain(|other| *other != entity);
+			if entities.is_empty() { self.tiles.remove(&tile); }
+		}
+	}
+}
+
+/// How many steps it takes to walk from one tile to another, since characters can't move diagonally.
+pub fn distance(from: Vector2i, to: Vector2i) -> i32 {
+	(from.x - to.x).abs() + (from.y - to.y).abs()
+}
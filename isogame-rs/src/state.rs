@@ -0,0 +1,27 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+/// Global story state that persists for the whole game, regardless of which level is loaded.
+#[derive(Clone, Default, Debug)]
+pub struct GameState {
+	flags: HashSet<String>,
+	counters: HashMap<String, i64>
+}
+
+impl GameState {
+	pub fn set_flag(&mut self, flag: &str) { self.flags.insert(flag.to_string()); }
+	
+	pub fn clear_flag(&mut self, flag: &str) { self.flags.remove(flag); }
+	
+	pub fn has_flag(&self, flag: &str) -> bool { self.flags.contains(flag) }
+	
+	pub fn get_counter(&self, counter: &str) -> i64 { *self.counters.get(counter).unwrap_or(&0) }
+	
+	pub fn set_counter(&mut self, counter: &str, value: i64) { self.counters.insert(counter.to_string(), value); }
+	
+	pub fn increment(&mut self, counter: &str) { *self.counters.entry(counter.to_string()).or_insert(0) += 1; }
+	
+	pub fn flags(&self) -> impl Iterator<Item = &String> { self.flags.iter() }
+	
+	pub fn counters(&self) -> impl Iterator<Item = (&String, &i64)> { self.counters.iter() }
+}
@@ -1,7 +1,9 @@
 use godot::prelude::*;
 use godot::builtin::Vector2;
 use godot::builtin::Vector2i;
+use godot::builtin::math::ApproxEq;
 use godot::classes::Input;
+use godot::classes::TileMapLayer;
 
 /// Tracks the different keyboard inputs registered with the game.
 pub enum KeyboardInput {
@@ -22,6 +24,36 @@ impl KeyboardInput {
 		None		
 	}
 	
+	/// Check whether the interact key was pressed this frame.
+	pub fn get_interact() -> bool {
+		let input = Input::singleton();
+		input.is_action_just_pressed("interact")
+	}
+	
+	/// Check whether the quest log key was pressed this frame.
+	pub fn get_quest_log() -> bool {
+		let input = Input::singleton();
+		input.is_action_just_pressed("quest_log")
+	}
+	
+	/// Check whether the sprint key is being held down.
+	pub fn get_sprint() -> bool {
+		let input = Input::singleton();
+		input.is_action_pressed("sprint")
+	}
+	
+	/// Check whether the zoom in key was pressed this frame.
+	pub fn get_zoom_in() -> bool {
+		let input = Input::singleton();
+		input.is_action_just_pressed("zoom_in")
+	}
+	
+	/// Check whether the zoom out key was pressed this frame.
+	pub fn get_zoom_out() -> bool {
+		let input = Input::singleton();
+		input.is_action_just_pressed("zoom_out")
+	}
+	
 	/// Get keypress and convert it into an `IsometricFacing` type, if applicable.
 	pub fn get_movement() -> Option<IsometricFacing> {
 		match KeyboardInput::get_key() {
@@ -49,45 +81,72 @@ pub enum IsometricFacing {
 
 
 impl IsometricFacing {
-	pub fn from_movement_vector(vector: Vector2, tile_width: f32) -> Option<Self> {
+	pub fn from_movement_vector(vector: Vector2, tile: TileGeometry) -> Option<Self> {
 		match vector {
-			val if val == IsometricFacing::NW.get_movement_vector(tile_width) => Some(IsometricFacing::NW),
-			val if val == IsometricFacing::NE.get_movement_vector(tile_width) => Some(IsometricFacing::NE),
-			val if val == IsometricFacing::SW.get_movement_vector(tile_width) => Some(IsometricFacing::SW),
-			val if val == IsometricFacing::SE.get_movement_vector(tile_width) => Some(IsometricFacing::SE),
+			val if val.approx_eq(&IsometricFacing::NW.get_movement_vector(tile)) => Some(IsometricFacing::NW),
+			val if val.approx_eq(&IsometricFacing::NE.get_movement_vector(tile)) => Some(IsometricFacing::NE),
+			val if val.approx_eq(&IsometricFacing::SW.get_movement_vector(tile)) => Some(IsometricFacing::SW),
+			val if val.approx_eq(&IsometricFacing::SE.get_movement_vector(tile)) => Some(IsometricFacing::SE),
 			_ => None
 		}
 	}
 
-	pub fn to_string(&self) -> String {
-		match self {
-			IsometricFacing::NW => "nw".to_string(),
-			IsometricFacing::NE => "ne".to_string(),
-			IsometricFacing::SW => "sw".to_string(),
-			IsometricFacing::SE => "se".to_string()
-		}		
-	}
-
-	pub fn get_animation(&self, animation: &str) -> String { format!("{}_{}", self.to_string(), animation) }
+	pub fn get_animation(&self, animation: &str) -> String { format!("{}_{}", self, animation) }
 
 	/// Get a directional movement vector with a magnitude of 1 isometric tile.
-	pub fn get_movement_vector(&self, tile_width: f32) -> Vector2 {
+	pub fn get_movement_vector(&self, tile: TileGeometry) -> Vector2 {
 		let vector = match self {
-			// Isometric tiles are twice as wide as they are tall.
-			// So movement is halved in the Y axis.
-			IsometricFacing::NW => Vector2::new(-1.0, -0.5),
-			IsometricFacing::NE => Vector2::new(1.0, -0.5),
-			IsometricFacing::SW => Vector2::new(-1.0, 0.5),
-			IsometricFacing::SE => Vector2::new(1.0, 0.5)
+			IsometricFacing::NW => Vector2::new(-1.0, -1.0),
+			IsometricFacing::NE => Vector2::new(1.0, -1.0),
+			IsometricFacing::SW => Vector2::new(-1.0, 1.0),
+			IsometricFacing::SE => Vector2::new(1.0, 1.0)
 		};
-		// Divide tile width by 2 because it's a vector.
-		// We only want to move half a tile in the X axis, and half a tile in the Y axis.
-		vector * (tile_width / 2.0)		
+		// Moving one tile along the grid moves half a tile across the screen, and half a tile down it.
+		vector * tile.size() / 2.0
 	}
 }
 
-/// Represents the result of an attempt to find a path to a target node or tile.
+/// The size of a level's tiles, which all movement on the grid is measured in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TileGeometry {
+	pub width: f32,
+	pub height: f32
+}
+
+impl Default for TileGeometry {
+	/// Our tilesets are 32x16, so this is right until we've been told otherwise.
+	fn default() -> Self {
+		Self { width: 32.0, height: 16.0 }
+	}
+}
 
+impl TileGeometry {
+	/// Read the tile size from a tilemap's tile set. Falls back to the default if it doesn't have one.
+	pub fn from_tilemap(tilemap: &TileMapLayer) -> Self {
+		match tilemap.get_tile_set() {
+			Some(tileset) => {
+				let size = tileset.get_tile_size().cast_float();
+				Self { width: size.x, height: size.y }
+			},
+			None => Self::default()
+		}
+	}
+	
+	pub fn size(&self) -> Vector2 { Vector2::new(self.width, self.height) }
+}
+
+impl std::fmt::Display for IsometricFacing {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			IsometricFacing::NW => write!(f, "nw"),
+			IsometricFacing::NE => write!(f, "ne"),
+			IsometricFacing::SW => write!(f, "sw"),
+			IsometricFacing::SE => write!(f, "se")
+		}
+	}
+}
+
+/// Represents the result of an attempt to find a path to a target node or tile.
 pub enum PathfindingResult {
 	NoPath,
 	ReachedTarget(Vector2i),	// Tile containing the target
@@ -0,0 +1,238 @@
+use std::collections::HashSet;
+use std::collections::VecDeque;
+
+use godot::prelude::*;
+use godot::classes::Node;
+use godot::classes::INode;
+use godot::classes::Node2D;
+use godot::classes::PackedScene;
+use godot::classes::ResourceLoader;
+use godot::classes::TileMapLayer;
+use godot::classes::DirAccess;
+
+use crate::creature::Creature;
+use crate::error::LogError;
+use crate::interact::Interactable;
+use crate::level;
+use crate::level::Level;
+use crate::level::SpawnPoint;
+use crate::level_manager::LevelWarp;
+use crate::npc::Npc;
+use crate::pickup::Pickup;
+use crate::player::Player;
+use crate::scenery::Scenery;
+
+/// Checks every level in the project for mistakes, then quits. Problems are printed as errors, and the exit code is 1 if any were found.
+///
+/// This is meant to be run headless from the command line:
+///
+/// ```text
+/// godot --headless --path isogame-godot res://validate_levels.tscn
+/// ```
+#[derive(GodotClass)]
+#[class(base=Node,init)]
+pub struct LevelValidator {
+	/// Directory to search for level scenes, including subdirectories.
+	#[export]
+	#[init(val = GString::from("res://"))]
+	directory: GString,
+	
+	base: Base<Node>
+}
+
+#[godot_api]
+impl INode for LevelValidator {
+	fn ready(&mut self) {
+		let levels = find_levels(&self.directory);
+		let mut problems = Vec::new();
+		
+		for path in levels.iter() {
+			problems.extend(validate_level(path).into_iter().map(|problem| format!("{}: {}", path, problem)));
+		}
+		
+		for problem in problems.iter() {
+			godot_error!("{}", problem);
+		}
+		godot_print!("Checked {} levels, found {} problems.", levels.len(), problems.len());
+		
+		let exit_code = if problems.is_empty() { 0 } else { 1 };
+		let mut tree = self.base().get_tree().unwrap();
+		tree.quit_ex().exit_code(exit_code).done();
+	}
+}
+
+/// Find the paths of every scene in a directory whose root node is a `Level`.
+fn find_levels(directory: &GString) -> Vec<GString> {
+	let mut levels = Vec::new();
+	let directory = directory.to_string();
+	let directory = directory.trim_end_matches('/');
+	
+	for file in DirAccess::get_files_at(directory).as_slice() {
+		let path = GString::from(format!("{}/{}", directory, file).as_str());
+		if file.to_string().ends_with(".tscn") && is_level(&path) {
+			levels.push(path);
+		}
+	}
+	
+	for subdirectory in DirAccess::get_directories_at(directory).as_slice() {
+		let path = GString::from(format!("{}/{}", directory, subdirectory).as_str());
+		levels.extend(find_levels(&path));
+	}
+	
+	levels
+}
+
+/// Whether the scene at a path exists and is a level, without instantiating it.
+fn is_level(path: &GString) -> bool {
+	if !ResourceLoader::singleton().exists(path) { return false; }
+	
+	let scene = match try_load::<PackedScene>(path) {
+		Ok(x) => x,
+		Err(_) => return false
+	};
+	
+	match scene.get_state() {
+		Some(state) => state.get_node_count() > 0 && state.get_node_type(0) == "Level".into(),
+		None => false
+	}
+}
+
+/// Load a level without adding it to the scene tree, so none of its nodes run. Generated levels are generated first,
+/// so that their tiles, warps and spawn points can be checked too.
+fn instantiate_level(path: &GString) -> Option<Gd<Level>> {
+	if !is_level(path) { return None; }
+	
+	let scene : Gd<PackedScene> = try_load(path).ok()?;
+	let mut level : Gd<Level> = scene.instantiate()?.try_cast().ok()?;
+	if level.bind_mut().generate().log_error(&format!("Couldn't generate {}", path)).is_none() {
+		level.free();
+		return None;
+	}
+	Some(level)
+}
+
+/// Find everything wrong with a single level.
+fn validate_level(path: &GString) -> Vec<String> {
+	let mut problems = Vec::new();
+	
+	let level = match instantiate_level(path) {
+		Some(x) => x,
+		None => return vec!["could not be loaded".to_string()]
+	};
+	
+	// Level fetches both layers when it is ready, and can't work without them.
+	let terrain = level.try_get_node_as::<TileMapLayer>("TerrainLayer");
+	let foreground = level.try_get_node_as::<TileMapLayer>("ForegroundLayer");
+	if terrain.is_none() { problems.push("missing TerrainLayer".to_string()); }
+	if foreground.is_none() { problems.push("missing ForegroundLayer".to_string()); }
+	
+	let mut warps = Vec::new();
+	let mut spawn_points = Vec::new();
+	let mut blocked = HashSet::new();
+	
+	for child in level.get_children().iter_shared() {
+		let name = child.get_name();
+		
+		if let Ok(warp) = child.clone().try_cast::<LevelWarp>() {
+			problems.extend(validate_warp(&warp));
+		}
+		
+		// Everything else is only a problem if it's on a tile that can't be walked on.
+		let (Some(terrain), Some(foreground)) = (terrain.as_ref(), foreground.as_ref()) else { continue; };
+		let node = match child.clone().try_cast::<Node2D>() {
+			Ok(x) => x,
+			Err(_) => continue
+		};
+		let tile = level::level_to_grid(terrain, node.get_position());
+		
+		if child.clone().try_cast::<LevelWarp>().is_ok() {
+			warps.push(tile);
+		} else if child.clone().try_cast::<SpawnPoint>().is_ok() {
+			if is_walkable(terrain, foreground, tile) {
+				spawn_points.push((name, tile));
+			} else {
+				problems.push(format!("spawn point {} is on an unwalkable tile {}", name, tile));
+			}
+		} else if is_entity(&child) && foreground.get_cell_source_id(tile) != -1 {
+			problems.push(format!("{} is inside a wall at {}", name, tile));
+		}
+		
+		// Static scenery blocks the navigation grid for good. Scenery objects can be pushed or broken, so they don't.
+		if child.is_in_group("scenery") && child.clone().try_cast::<Scenery>().is_err() {
+			blocked.insert(tile);
+		}
+	}
+	
+	// Every spawn point must be reachable on foot from the ways into the level. Without any warps, the first spawn
+	// point is where the player starts.
+	if let (Some(terrain), Some(foreground)) = (terrain.as_ref(), foreground.as_ref()) {
+		let origins = if warps.is_empty() { spawn_points.iter().map(|(_, tile)| *tile).take(1).collect() } else { warps };
+		let reachable = flood_fill(terrain.clone(), &origins, |tile| is_walkable(terrain, foreground, tile) && !blocked.contains(&tile));
+		for (name, tile) in spawn_points.iter() {
+			if !reachable.contains(tile) {
+				problems.push(format!("spawn point {} at {} can't be reached from the level's warps", name, tile));
+			}
+		}
+	}
+	
+	level.free();
+	problems
+}
+
+/// Every tile that can be walked to from any of the origins, a step at a time over open tiles.
+/// The origins themselves are included, even if they aren't open.
+fn flood_fill(mut terrain: Gd<TileMapLayer>, origins: &[Vector2i], open: impl Fn(Vector2i) -> bool) -> HashSet<Vector2i> {
+	let mut reached : HashSet<Vector2i> = origins.iter().copied().collect();
+	let mut frontier : VecDeque<Vector2i> = origins.iter().copied().collect();
+	
+	while let Some(tile) = frontier.pop_front() {
+		for neighbour in terrain.get_surrounding_cells(tile).iter_shared() {
+			if open(neighbour) && reached.insert(neighbour) { frontier.push_back(neighbour); }
+		}
+	}
+	reached
+}
+
+/// Check that a warp leads to a level, and to a spawn point that exists in it.
+fn validate_warp(warp: &Gd<LevelWarp>) -> Vec<String> {
+	let name = warp.get_name();
+	let destination = warp.bind().get_level();
+	let spawn = warp.bind().get_spawn();
+	
+	if destination.is_empty() {
+		return vec![format!("warp {} has no destination level", name)];
+	}
+	if !ResourceLoader::singleton().exists(&destination) {
+		return vec![format!("warp {} leads to {}, which doesn't exist", name, destination)];
+	}
+	
+	let level = match instantiate_level(&destination) {
+		Some(x) => x,
+		None => return vec![format!("warp {} leads to {}, which isn't a level", name, destination)]
+	};
+	
+	let found = level.bind().find_spawn_point(&spawn.to_string()).is_some();
+	level.free();
+	
+	if found {
+		Vec::new()
+	} else {
+		vec![format!("warp {} leads to spawn point '{}', which {} doesn't have", name, spawn, destination)]
+	}
+}
+
+/// Whether a tile has floor to stand on and no wall in the way.
+fn is_walkable(terrain: &TileMapLayer, foreground: &TileMapLayer, tile: Vector2i) -> bool {
+	terrain.get_cell_source_id(tile) != -1 && foreground.get_cell_source_id(tile) == -1
+}
+
+/// Whether a node occupies a tile, and so must not be placed inside a wall.
+fn is_entity(node: &Gd<Node>) -> bool {
+	node.clone().try_cast::<Player>().is_ok()
+		|| node.clone().try_cast::<Creature>().is_ok()
+		|| node.clone().try_cast::<Npc>().is_ok()
+		|| node.clone().try_cast::<Scenery>().is_ok()
+		|| node.clone().try_cast::<Interactable>().is_ok()
+		|| node.clone().try_cast::<Pickup>().is_ok()
+		|| node.is_in_group("scenery")
+}
@@ -0,0 +1,51 @@
+//! Runs the scripted level tests in `isogame-godot/tests` with a headless Godot, which doesn't need a window or a GPU.
+//!
+//! Set `GODOT` to the path of a Godot 4 executable if it isn't on the `PATH` as `godot`. If Godot can't be found,
+//! the tests are skipped rather than failed, so that `cargo test` still works without it.
+
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+/// How long the level tests may take before they're assumed to be stuck, for example on a test that never finishes.
+const TIMEOUT: Duration = Duration::from_secs(300);
+
+#[test]
+fn level_scripts() {
+	let godot = env::var("GODOT").unwrap_or_else(|_| "godot".to_string());
+	let project = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../isogame-godot");
+	
+	// A fresh checkout has no imported resources yet, and the scenes can't load without them.
+	let imported = match Command::new(&godot).arg("--headless").arg("--path").arg(&project).arg("--import").status() {
+		Ok(x) => x,
+		Err(_) => {
+			eprintln!("Skipping level tests, because '{}' couldn't be run. Set GODOT to the path of a Godot 4 executable.", godot);
+			return;
+		}
+	};
+	assert!(imported.success(), "Godot failed to import the project");
+	
+	// A fixed frame rate makes every physics frame the same length, and runs them as fast as possible.
+	let mut child = Command::new(&godot)
+		.arg("--headless")
+		.args(["--fixed-fps", "60"])
+		.arg("--path").arg(&project)
+		.arg("res://run_tests.tscn")
+		.spawn()
+		.expect("Godot couldn't be run");
+	
+	let started = Instant::now();
+	let status = loop {
+		if let Some(status) = child.try_wait().expect("couldn't wait for Godot") { break status; }
+		if started.elapsed() > TIMEOUT {
+			let _ = child.kill();
+			let _ = child.wait();
+			panic!("the level tests didn't finish within {} seconds", TIMEOUT.as_secs());
+		}
+		thread::sleep(Duration::from_millis(100));
+	};
+	assert!(status.success(), "some level tests failed, see the errors above");
+}